@@ -0,0 +1,11 @@
+
+vec2 lightmapUtil_10023_11020_2129c0(vec2 tc1){
+    float pack8 = floor(tc1.x * 255.0);
+    vec2 uv = vec2(mod(pack8, 16.0), floor(pack8 / 16.0));
+    return clamp(uv * 0.0625, 0.0, 1.0);
+}
+#ifdef a_texcoord1
+  #undef a_texcoord1
+#endif
+#define a_texcoord1 lightmapUtil_10023_11020_2129c0(a_texcoord1)
+    
@@ -0,0 +1,11 @@
+
+vec2 lightmapUtil_10023_13028_ff75e2(vec2 tc1){
+    float pack16 = floor(tc1.y * 65535.0 + 0.5);
+    vec2 uv = vec2(mod(floor(pack16 / 16.0), 16.0), mod(pack16, 16.0));
+    return clamp(uv * 0.066666, 0.0, 1.0);
+}
+#ifdef a_texcoord1
+  #undef a_texcoord1
+#endif
+#define a_texcoord1 lightmapUtil_10023_13028_ff75e2(a_texcoord1)
+    
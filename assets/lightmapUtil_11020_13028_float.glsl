@@ -0,0 +1,11 @@
+
+vec2 lightmapUtil_11020_13028_994b3a(vec2 tc1){
+    float pack16 = floor(tc1.y * 65535.0 + 0.5);
+    vec2 uv = vec2(mod(floor(pack16 / 16.0), 16.0), mod(pack16, 16.0));
+    return vec2((uv.y * 16.0 + uv.x) / 255.0, 0.0);
+}
+#ifdef a_texcoord1
+  #undef a_texcoord1
+#endif
+#define a_texcoord1 lightmapUtil_11020_13028_994b3a(a_texcoord1)
+    
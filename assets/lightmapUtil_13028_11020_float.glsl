@@ -0,0 +1,11 @@
+
+vec2 lightmapUtil_13028_11020_db68b5(vec2 tc1){
+    float pack8 = floor(tc1.x * 255.0);
+    vec2 uv = vec2(mod(pack8, 16.0), floor(pack8 / 16.0));
+    return vec2(0.0, (uv.x * 16.0 + uv.y) / 65535.0);
+}
+#ifdef a_texcoord1
+  #undef a_texcoord1
+#endif
+#define a_texcoord1 lightmapUtil_13028_11020_db68b5(a_texcoord1)
+    
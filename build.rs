@@ -2,5 +2,6 @@ fn main() {
     cc::Build::new()
         .cpp(true)
         .file("src/string.cpp")
+        .file("src/dlopen.cpp")
         .compile("stringstub");
 }
@@ -1,21 +1,42 @@
-//Explanation: Aasset is NOT thread-safe anyways so we will not try adding thread safety either
-#![allow(static_mut_refs)]
 use crate::{
     loader::{Buffer, FileLoader},
+    plthook::{find_export, PltHook},
     LockResultExt,
 };
+use bhook::hook_fn;
 use libc::{c_char, c_int, c_void, off64_t, off_t, size_t};
 use ndk_sys::{AAsset, AAssetManager};
 use std::{
+    cell::Cell,
     collections::HashMap,
     ffi::{CStr, OsStr},
     io::{self, Read, Seek},
     os::unix::ffi::OsStrExt,
     path::Path,
     //    ptr,
-    sync::{LazyLock, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex, RwLock,
+    },
 };
-static mut MC_FILELOADER: LazyLock<FileLoader> = LazyLock::new(|| FileLoader::new());
+// Assets can be opened from any thread, the game's loader threads included
+static MC_FILELOADER: LazyLock<Mutex<FileLoader>> = LazyLock::new(|| Mutex::new(FileLoader::new()));
+thread_local! {
+    // Set while this thread looks a file up, the game's pack loading can open assets again
+    static IN_LOOKUP: Cell<bool> = const { Cell::new(false) };
+}
+/// Clears IN_LOOKUP when the lookup ends, even if it panics
+struct LookupGuard;
+impl LookupGuard {
+    fn enter() -> Option<Self> {
+        (!IN_LOOKUP.replace(true)).then_some(Self)
+    }
+}
+impl Drop for LookupGuard {
+    fn drop(&mut self) {
+        IN_LOOKUP.set(false);
+    }
+}
 // This makes me feel wrong... but all we will do is compare the pointer
 // and the struct will be used in a mutex so this is safe??
 #[derive(PartialEq, Eq, Hash)]
@@ -23,28 +44,133 @@ struct AAssetPtr(*const ndk_sys::AAsset);
 unsafe impl Send for AAssetPtr {}
 
 // The assets we have registered to replace data about
-static mut WANTED_ASSETS: LazyLock<Mutex<HashMap<AAssetPtr, Buffer>>> =
+static WANTED_ASSETS: LazyLock<Mutex<HashMap<AAssetPtr, Buffer>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
-pub unsafe extern "C" fn open(
-    man: *mut AAssetManager,
-    fname: *const c_char,
-    mode: c_int,
-) -> *mut AAsset {
+struct InstalledHook {
+    library: String,
+    symbol: &'static str,
+    hook: PltHook,
+}
+// The GOT entries we replaced, in every hooked library
+static HOOKS: RwLock<Vec<InstalledHook>> = RwLock::new(Vec::new());
+
+/// Keep installed hooks so calls can be passed on to what the library used before
+pub fn register_hooks(library: &str, hooks: impl IntoIterator<Item = (&'static str, PltHook)>) {
+    let hooks = hooks.into_iter().map(|(symbol, hook)| InstalledHook {
+        library: library.to_owned(),
+        symbol,
+        hook,
+    });
+    HOOKS.write().ignore_poison().extend(hooks);
+}
+pub fn hooks_installed() -> bool {
+    !HOOKS.read().ignore_poison().is_empty()
+}
+/// Restore the GOT entries we replaced in the libraries `filter` picks
+pub fn uninstall_hooks(mut filter: impl FnMut(&str) -> bool) {
+    let removed = {
+        let mut hooks = HOOKS.write().ignore_poison();
+        let (removed, kept) = std::mem::take(&mut *hooks)
+            .into_iter()
+            .partition(|installed| filter(&installed.library));
+        *hooks = kept;
+        removed
+    };
+    for InstalledHook {
+        library,
+        symbol,
+        hook,
+    } in removed
+    {
+        if let Err(e) = hook.uninstall() {
+            log::error!("Failed to unhook {symbol} in {library}: {e}");
+        }
+    }
+}
+
+macro_rules! originals {
+    ($($sym:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        /// Calls to whatever a GOT entry pointed to before we hooked it,
+        /// which is libandroid itself unless another mod got there first.
+        /// We can't tell which library called us, so the first hooked library's original is used
+        #[allow(non_snake_case)]
+        mod original {
+            use super::*;
+            $(pub unsafe fn $sym($($arg: $ty),*) -> $ret {
+                let original = HOOKS
+                    .read()
+                    .ignore_poison()
+                    .iter()
+                    .find(|installed| installed.symbol == stringify!($sym))
+                    .map(|installed| installed.hook.original::<unsafe extern "C" fn($($ty),*) -> $ret>());
+                match original {
+                    Some(original) => original($($arg),*),
+                    None => ndk_sys::$sym($($arg),*),
+                }
+            })*
+        }
+    };
+}
+originals! {
+    AAssetManager_open(man: *mut AAssetManager, fname: *const c_char, mode: c_int) -> *mut AAsset;
+    AAsset_read(aasset: *mut AAsset, buf: *mut c_void, count: size_t) -> c_int;
+    AAsset_close(aasset: *mut AAsset) -> ();
+    AAsset_seek(aasset: *mut AAsset, off: off_t, whence: c_int) -> off_t;
+    AAsset_seek64(aasset: *mut AAsset, off: off64_t, whence: c_int) -> off64_t;
+    AAsset_getLength(aasset: *mut AAsset) -> off_t;
+    AAsset_getLength64(aasset: *mut AAsset) -> off64_t;
+    AAsset_getRemainingLength(aasset: *mut AAsset) -> off_t;
+    AAsset_getRemainingLength64(aasset: *mut AAsset) -> off64_t;
+    AAsset_openFileDescriptor(aasset: *mut AAsset, out_start: *mut off_t, out_len: *mut off_t) -> c_int;
+    AAsset_openFileDescriptor64(aasset: *mut AAsset, out_start: *mut off64_t, out_len: *mut off64_t) -> c_int;
+    AAsset_getBuffer(aasset: *mut AAsset) -> *const c_void;
+    AAsset_isAllocated(aasset: *mut AAsset) -> c_int;
+}
+
+/// Check an asset that was just opened, and keep a replacement for it if we have one
+unsafe fn after_open(asset: *mut AAsset, fname: *const c_char) {
     // This is where UB can happen, but we are merely a hook.
-    let asset = ndk_sys::AAssetManager_open(man, fname, mode);
     let c_str = unsafe { CStr::from_ptr(fname) };
     let raw_cstr = c_str.to_bytes();
     let os_str = OsStr::from_bytes(raw_cstr);
     let c_path: &Path = Path::new(os_str);
-    //    let mut sus = MC_FILELOADER.lock().ignore_poison();
-    if let Some(yay) = MC_FILELOADER.get_file(c_path) {
+    // Opens made by our own lookup get the apk file, the loader is already locked by this thread
+    let Some(_guard) = LookupGuard::enter() else {
+        return;
+    };
+    let replacement = MC_FILELOADER
+        .lock()
+        .ignore_poison()
+        .get_file(c_path, || asset_bytes(asset));
+    if let Some(yay) = replacement {
         WANTED_ASSETS
             .lock()
             .ignore_poison()
             .insert(AAssetPtr(asset), yay);
     }
-    asset
+}
+/// Get the whole contents of an apk asset
+unsafe fn asset_bytes<'a>(asset: *mut AAsset) -> Option<&'a [u8]> {
+    if asset.is_null() {
+        return None;
+    }
+    let buffer = original::AAsset_getBuffer(asset);
+    let len = usize::try_from(original::AAsset_getLength64(asset)).ok()?;
+    if buffer.is_null() {
+        return None;
+    }
+    Some(core::slice::from_raw_parts(buffer.cast(), len))
+}
+unsafe fn before_close(aasset: *mut AAsset) {
+    let buffer = WANTED_ASSETS
+        .lock()
+        .ignore_poison()
+        .remove(&AAssetPtr(aasset));
+    // A close from inside a lookup would wait on the lock this thread holds, drop the buffer instead
+    if let (Some(buffer), Some(_guard)) = (buffer, LookupGuard::enter()) {
+        MC_FILELOADER.lock().ignore_poison().return_buffer(buffer);
+    }
 }
 macro_rules! handle_result {
     ($expr:expr) => {
@@ -58,117 +184,237 @@ macro_rules! handle_result {
     };
 }
 
-pub unsafe extern "C" fn seek64(aasset: *mut AAsset, off: off64_t, whence: c_int) -> off64_t {
-    let mut wanted = WANTED_ASSETS.lock().ignore_poison();
-    let Some(file) = wanted.get_mut(&AAssetPtr(aasset)) else {
-        return ndk_sys::AAsset_seek64(aasset, off, whence);
-    };
-    handle_result!(seek_facade(off, whence, file).try_into())
+/// Run `f` on our replacement for an asset, None if we are not replacing it.
+// The lock is gone before the caller falls back to the original,
+// which can end up in our inline hooks again
+unsafe fn with_wanted<T>(aasset: *mut AAsset, f: impl FnOnce(&mut Buffer) -> T) -> Option<T> {
+    WANTED_ASSETS
+        .lock()
+        .ignore_poison()
+        .get_mut(&AAssetPtr(aasset))
+        .map(f)
 }
 
-pub unsafe extern "C" fn seek(aasset: *mut AAsset, off: off_t, whence: c_int) -> off_t {
-    let mut wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    let Some(file) = wanted_assets.get_mut(&AAssetPtr(aasset)) else {
-        return ndk_sys::AAsset_seek(aasset, off, whence);
-    };
-    handle_result!(seek_facade(off.into(), whence, file).try_into())
+unsafe fn try_seek64(aasset: *mut AAsset, off: off64_t, whence: c_int) -> Option<off64_t> {
+    with_wanted(aasset, |file| {
+        handle_result!(seek_facade(off, whence, file).try_into())
+    })
 }
 
-pub unsafe extern "C" fn read(aasset: *mut AAsset, buf: *mut c_void, count: size_t) -> c_int {
-    let mut wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    let Some(file) = wanted_assets.get_mut(&AAssetPtr(aasset)) else {
-        return ndk_sys::AAsset_read(aasset, buf, count);
-    };
-    // Reuse buffer given by caller
-    let rs_buffer = core::slice::from_raw_parts_mut(buf as *mut u8, count);
-    let read_total = handle_result!((*file).read(rs_buffer));
-    handle_result!(read_total.try_into())
+unsafe fn try_seek(aasset: *mut AAsset, off: off_t, whence: c_int) -> Option<off_t> {
+    with_wanted(aasset, |file| {
+        handle_result!(seek_facade(off.into(), whence, file).try_into())
+    })
 }
 
-pub unsafe extern "C" fn len(aasset: *mut AAsset) -> off_t {
-    let wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    let Some(file) = wanted_assets.get(&AAssetPtr(aasset)) else {
-        return ndk_sys::AAsset_getLength(aasset);
-    };
-    handle_result!(file.get_ref().len().try_into())
+unsafe fn try_read(aasset: *mut AAsset, buf: *mut c_void, count: size_t) -> Option<c_int> {
+    with_wanted(aasset, |file| {
+        // Reuse buffer given by caller
+        let rs_buffer = core::slice::from_raw_parts_mut(buf as *mut u8, count);
+        let read_total = handle_result!((*file).read(rs_buffer));
+        handle_result!(read_total.try_into())
+    })
 }
 
-pub unsafe extern "C" fn len64(aasset: *mut AAsset) -> off64_t {
-    let wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    let Some(file) = wanted_assets.get(&AAssetPtr(aasset)) else {
-        return ndk_sys::AAsset_getLength64(aasset);
-    };
-    handle_result!(file.get_ref().len().try_into())
+unsafe fn try_len(aasset: *mut AAsset) -> Option<off_t> {
+    with_wanted(aasset, |file| {
+        handle_result!(file.get_ref().len().try_into())
+    })
 }
 
-pub unsafe extern "C" fn rem(aasset: *mut AAsset) -> off_t {
-    let wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    let Some(file) = wanted_assets.get(&AAssetPtr(aasset)) else {
-        return ndk_sys::AAsset_getRemainingLength(aasset);
-    };
-    handle_result!((file.get_ref().len() - file.position() as usize).try_into())
+unsafe fn try_len64(aasset: *mut AAsset) -> Option<off64_t> {
+    with_wanted(aasset, |file| {
+        handle_result!(file.get_ref().len().try_into())
+    })
 }
 
-pub unsafe extern "C" fn rem64(aasset: *mut AAsset) -> off64_t {
-    let wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    let Some(file) = wanted_assets.get(&AAssetPtr(aasset)) else {
-        return ndk_sys::AAsset_getRemainingLength64(aasset);
-    };
-    handle_result!((file.get_ref().len() - file.position() as usize).try_into())
+unsafe fn try_rem(aasset: *mut AAsset) -> Option<off_t> {
+    with_wanted(aasset, |file| {
+        handle_result!((file.get_ref().len() - file.position() as usize).try_into())
+    })
 }
 
-pub unsafe extern "C" fn close(aasset: *mut AAsset) {
-    let mut wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    // if let Some(buffer) = wanted_assets.remove(&AAssetPtr(aasset)) {
-    //     MC_FILELOADER.last_buffer = Some(buffer);
-    // }
-    ndk_sys::AAsset_close(aasset);
+unsafe fn try_rem64(aasset: *mut AAsset) -> Option<off64_t> {
+    with_wanted(aasset, |file| {
+        handle_result!((file.get_ref().len() - file.position() as usize).try_into())
+    })
 }
 
-pub unsafe extern "C" fn get_buffer(aasset: *mut AAsset) -> *const c_void {
-    let mut wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    let Some(file) = wanted_assets.get_mut(&AAssetPtr(aasset)) else {
-        return ndk_sys::AAsset_getBuffer(aasset);
-    };
+unsafe fn try_get_buffer(aasset: *mut AAsset) -> Option<*const c_void> {
     // Let's hope this does not go boom boom
-    file.get_ref().as_ptr().cast()
+    with_wanted(aasset, |file| file.get_ref().as_ptr().cast())
 }
 
-pub unsafe extern "C" fn fd_dummy(
+unsafe fn try_fd_dummy(
     aasset: *mut AAsset,
-    out_start: *mut off_t,
-    out_len: *mut off_t,
-) -> c_int {
-    let wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    match wanted_assets.get(&AAssetPtr(aasset)) {
-        Some(_) => {
-            log::error!("WE GOT BUSTED NOOO");
-            -1
-        }
-        None => ndk_sys::AAsset_openFileDescriptor(aasset, out_start, out_len),
-    }
+    _out_start: *mut off_t,
+    _out_len: *mut off_t,
+) -> Option<c_int> {
+    with_wanted(aasset, |_| {
+        log::error!("WE GOT BUSTED NOOO");
+        -1
+    })
 }
 
-pub unsafe extern "C" fn fd_dummy64(
+unsafe fn try_fd_dummy64(
     aasset: *mut AAsset,
-    out_start: *mut off64_t,
-    out_len: *mut off64_t,
-) -> c_int {
-    let wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    match wanted_assets.get(&AAssetPtr(aasset)) {
-        Some(_) => {
-            log::error!("WE GOT BUSTED NOOO");
-            -1
+    _out_start: *mut off64_t,
+    _out_len: *mut off64_t,
+) -> Option<c_int> {
+    with_wanted(aasset, |_| {
+        log::error!("WE GOT BUSTED NOOO");
+        -1
+    })
+}
+
+unsafe fn try_is_alloc(aasset: *mut AAsset) -> Option<c_int> {
+    with_wanted(aasset, |_| false as c_int)
+}
+
+pub unsafe extern "C" fn open(
+    man: *mut AAssetManager,
+    fname: *const c_char,
+    mode: c_int,
+) -> *mut AAsset {
+    let asset = original::AAssetManager_open(man, fname, mode);
+    after_open(asset, fname);
+    asset
+}
+
+pub unsafe extern "C" fn close(aasset: *mut AAsset) {
+    before_close(aasset);
+    original::AAsset_close(aasset);
+}
+
+// Our answer if the asset is ours, else whatever the original says
+macro_rules! plt_hooks {
+    ($($plt:ident => $try:ident, $sym:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(pub unsafe extern "C" fn $plt($($arg: $ty),*) -> $ret {
+            match $try($($arg),*) {
+                Some(ret) => ret,
+                None => original::$sym($($arg),*),
+            }
+        })*
+    };
+}
+plt_hooks! {
+    seek64 => try_seek64, AAsset_seek64(aasset: *mut AAsset, off: off64_t, whence: c_int) -> off64_t;
+    seek => try_seek, AAsset_seek(aasset: *mut AAsset, off: off_t, whence: c_int) -> off_t;
+    read => try_read, AAsset_read(aasset: *mut AAsset, buf: *mut c_void, count: size_t) -> c_int;
+    len => try_len, AAsset_getLength(aasset: *mut AAsset) -> off_t;
+    len64 => try_len64, AAsset_getLength64(aasset: *mut AAsset) -> off64_t;
+    rem => try_rem, AAsset_getRemainingLength(aasset: *mut AAsset) -> off_t;
+    rem64 => try_rem64, AAsset_getRemainingLength64(aasset: *mut AAsset) -> off64_t;
+    get_buffer => try_get_buffer, AAsset_getBuffer(aasset: *mut AAsset) -> *const c_void;
+    fd_dummy => try_fd_dummy, AAsset_openFileDescriptor(aasset: *mut AAsset, out_start: *mut off_t, out_len: *mut off_t) -> c_int;
+    fd_dummy64 => try_fd_dummy64, AAsset_openFileDescriptor64(aasset: *mut AAsset, out_start: *mut off64_t, out_len: *mut off64_t) -> c_int;
+    is_alloc => try_is_alloc, AAsset_isAllocated(aasset: *mut AAsset) -> c_int;
+}
+
+// Inline hooks patch libandroid itself, so they can't be removed, only turned into passthroughs
+static INLINE_ENABLED: AtomicBool = AtomicBool::new(false);
+static INLINE_HOOKED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Inline hook the given libandroid exports, for when the PLT of a library can't be used.
+/// Symbols that are already inline hooked are skipped
+pub fn install_inline_hooks(symbols: &[String]) {
+    let mut hooked = INLINE_HOOKED.lock().ignore_poison();
+    for symbol in symbols {
+        if hooked.contains(symbol) {
+            continue;
+        }
+        let Some(addr) = find_export(c"libandroid.so", symbol) else {
+            log::error!("Cannot find {symbol} in libandroid, not inline hooking it");
+            continue;
+        };
+        if unsafe { inline::install(symbol, addr) } {
+            log::info!("Inline hooked {symbol}");
+            hooked.push(symbol.clone());
+        } else {
+            log::error!("{symbol} is not an AAsset function we can inline hook");
         }
-        None => ndk_sys::AAsset_openFileDescriptor64(aasset, out_start, out_len),
     }
+    INLINE_ENABLED.store(true, Ordering::Release);
+}
+pub fn is_inline_hooked(symbol: &str) -> bool {
+    INLINE_HOOKED
+        .lock()
+        .ignore_poison()
+        .iter()
+        .any(|s| s == symbol)
+}
+/// Make the inline hooks pass every call through
+pub fn disable_inline_hooks() {
+    INLINE_ENABLED.store(false, Ordering::Release);
 }
 
-pub unsafe extern "C" fn is_alloc(aasset: *mut AAsset) -> c_int {
-    let wanted_assets = WANTED_ASSETS.lock().ignore_poison();
-    match wanted_assets.get(&AAssetPtr(aasset)) {
-        Some(_) => false as c_int,
-        None => ndk_sys::AAsset_isAllocated(aasset),
+// Same as plt_hooks, but the originals come from the trampolines
+macro_rules! inline_hooks {
+    ($($try:ident, $sym:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(hook_fn! {
+            fn $sym($($arg: $ty),*) -> $ret = {
+                if !INLINE_ENABLED.load(Ordering::Acquire) {
+                    return call_original($($arg),*);
+                }
+                match unsafe { $try($($arg),*) } {
+                    Some(ret) => ret,
+                    None => call_original($($arg),*),
+                }
+            }
+        })*
+    };
+}
+#[allow(non_snake_case)]
+mod inline {
+    use super::*;
+    inline_hooks! {
+        try_seek64, AAsset_seek64(aasset: *mut AAsset, off: off64_t, whence: c_int) -> off64_t;
+        try_seek, AAsset_seek(aasset: *mut AAsset, off: off_t, whence: c_int) -> off_t;
+        try_read, AAsset_read(aasset: *mut AAsset, buf: *mut c_void, count: size_t) -> c_int;
+        try_len, AAsset_getLength(aasset: *mut AAsset) -> off_t;
+        try_len64, AAsset_getLength64(aasset: *mut AAsset) -> off64_t;
+        try_rem, AAsset_getRemainingLength(aasset: *mut AAsset) -> off_t;
+        try_rem64, AAsset_getRemainingLength64(aasset: *mut AAsset) -> off64_t;
+        try_get_buffer, AAsset_getBuffer(aasset: *mut AAsset) -> *const c_void;
+        try_fd_dummy, AAsset_openFileDescriptor(aasset: *mut AAsset, out_start: *mut off_t, out_len: *mut off_t) -> c_int;
+        try_fd_dummy64, AAsset_openFileDescriptor64(aasset: *mut AAsset, out_start: *mut off64_t, out_len: *mut off64_t) -> c_int;
+        try_is_alloc, AAsset_isAllocated(aasset: *mut AAsset) -> c_int;
+    }
+    hook_fn! {
+        fn AAssetManager_open(man: *mut AAssetManager, fname: *const c_char, mode: c_int) -> *mut AAsset = {
+            let asset = call_original(man, fname, mode);
+            if INLINE_ENABLED.load(Ordering::Acquire) {
+                unsafe { after_open(asset, fname) };
+            }
+            asset
+        }
+    }
+    hook_fn! {
+        fn AAsset_close(aasset: *mut AAsset) -> () = {
+            // Assets opened while enabled still need their buffers dropped
+            unsafe { before_close(aasset) };
+            call_original(aasset)
+        }
+    }
+    /// Hook the function at `addr` with the inline hook for `symbol`
+    pub unsafe fn install(symbol: &str, addr: *mut u8) -> bool {
+        match symbol {
+            "AAssetManager_open" => AAssetManager_open::hook_address(addr),
+            "AAsset_close" => AAsset_close::hook_address(addr),
+            "AAsset_read" => AAsset_read::hook_address(addr),
+            "AAsset_seek" => AAsset_seek::hook_address(addr),
+            "AAsset_seek64" => AAsset_seek64::hook_address(addr),
+            "AAsset_getLength" => AAsset_getLength::hook_address(addr),
+            "AAsset_getLength64" => AAsset_getLength64::hook_address(addr),
+            "AAsset_getRemainingLength" => AAsset_getRemainingLength::hook_address(addr),
+            "AAsset_getRemainingLength64" => AAsset_getRemainingLength64::hook_address(addr),
+            "AAsset_openFileDescriptor" => AAsset_openFileDescriptor::hook_address(addr),
+            "AAsset_openFileDescriptor64" => AAsset_openFileDescriptor64::hook_address(addr),
+            "AAsset_getBuffer" => AAsset_getBuffer::hook_address(addr),
+            "AAsset_isAllocated" => AAsset_isAllocated::hook_address(addr),
+            _ => return false,
+        }
+        true
     }
 }
 
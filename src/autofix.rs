@@ -0,0 +1,179 @@
+mod lightmap;
+mod texturelod;
+
+use crate::jniopts::Options;
+use crate::materialbin::{BgfxShader, CompiledMaterialDefinition, MinecraftVersion};
+use bstr::ByteSlice;
+use scroll::Pread;
+
+/// Find the first version in the list that can parse this material
+pub fn detect_version(data: &[u8], versions: &[MinecraftVersion]) -> Option<MinecraftVersion> {
+    versions.iter().copied().find(|version| {
+        data.pread_with::<CompiledMaterialDefinition>(0, *version)
+            .is_ok()
+    })
+}
+
+/// Rewrite a material.bin so the game can read it, returns None if it can be used as is
+pub fn autofix_material(data: &[u8], target: MinecraftVersion, opts: &Options) -> Option<Vec<u8>> {
+    // Try the game format first, a pack made for it only needs the shader fixes
+    let mut versions = std::iter::once(&target).chain(&opts.autofixer_versions);
+    let (mut material, version) = versions.find_map(|version| {
+        data.pread_with::<CompiledMaterialDefinition>(0, *version)
+            .ok()
+            .map(|material| (material, *version))
+    })?;
+    let mut changed = version != target;
+    if changed {
+        log::info!("Updating material from {:?} to {:?}", version, target);
+    }
+    if opts.handle_lightmaps {
+        changed |= lightmap::fix_lightmaps(&mut material, version, target) != 0;
+    }
+    if opts.handle_texturelods {
+        changed |= texturelod::fix_texture_lods(&mut material) != 0;
+    }
+    if !changed {
+        return None;
+    }
+    let mut output = Vec::with_capacity(data.len());
+    if let Err(e) = material.write(&mut output, target) {
+        log::error!("Failed to write updated material: {e}");
+        return None;
+    }
+    Some(output)
+}
+
+/// Run `edit` on the source of every glsl/essl shader, `edit` returns whether it changed it
+fn edit_glsl_shaders(
+    material: &mut CompiledMaterialDefinition,
+    vertex_only: bool,
+    mut edit: impl FnMut(&mut Vec<u8>) -> bool,
+) -> usize {
+    let mut edited = 0;
+    let codes = material
+        .passes
+        .iter_mut()
+        .flat_map(|(_, pass)| pass.variants.iter_mut())
+        .flat_map(|variant| variant.shader_codes.iter_mut());
+    for (stage, code) in codes {
+        let is_glsl =
+            stage.platform_name.starts_with("ESSL") || stage.platform_name.starts_with("GLSL");
+        if !is_glsl || (vertex_only && stage.stage_name != "Vertex") {
+            continue;
+        }
+        let mut shader = match BgfxShader::read(&code.bgfx_shader_data) {
+            Ok(shader) => shader,
+            Err(e) => {
+                log::warn!("Skipping unreadable {} shader: {e}", stage.platform_name);
+                continue;
+            }
+        };
+        if !edit(&mut shader.code) {
+            continue;
+        }
+        if let Some(data) = shader.write() {
+            code.bgfx_shader_data = data;
+            edited += 1;
+        }
+    }
+    edited
+}
+
+/// Check for essl 300+ or desktop glsl 130+, which dropped the old sampler functions
+/// and added integer types. Shaders without a #version are essl 100 / glsl 110
+fn is_modern_profile(code: &[u8]) -> bool {
+    let Some(line) = code
+        .lines()
+        .map(|line| line.trim_ascii())
+        .find(|line| line.starts_with(b"#version"))
+    else {
+        return false;
+    };
+    let mut words = line.fields().skip(1);
+    let Some(version) = words.next().and_then(atoi::atoi::<u32>) else {
+        return false;
+    };
+    match words.next() {
+        Some(b"es") => version >= 300,
+        _ => version >= 130,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    const VERTEX: &[u8] = b"#version 300 es\nin vec2 a_texcoord1;\nvoid main() { gl_Position = vec4(a_texcoord1, 0.0, 1.0); }\n";
+
+    /// The material fixture of `release` with its shaders swapped for one that reads the lightmap
+    fn lightmap_material(release: &str, version: MinecraftVersion) -> Vec<u8> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/materialbin")
+            .join(release)
+            .join("Fixture.material.bin");
+        let data = fs::read(path).expect("fixture");
+        let mut material: CompiledMaterialDefinition =
+            data.pread_with(0, version).expect("fixture parses");
+        let codes = material
+            .passes
+            .iter_mut()
+            .flat_map(|(_, pass)| pass.variants.iter_mut())
+            .flat_map(|variant| variant.shader_codes.iter_mut());
+        for (stage, code) in codes {
+            stage.stage_name = "Vertex".to_string();
+            let mut shader = BgfxShader::read(&code.bgfx_shader_data).expect("bgfx shader");
+            shader.code = VERTEX.to_vec();
+            code.bgfx_shader_data = shader.write().expect("bgfx shader");
+        }
+        let mut output = Vec::new();
+        material.write(&mut output, version).expect("write");
+        output
+    }
+
+    /// The encodings in the name of the snippet the autofixer put in, None if nothing changed
+    fn spliced_snippet(data: &[u8], target: MinecraftVersion) -> Option<String> {
+        let opts = Options {
+            handle_texturelods: false,
+            ..Options::default()
+        };
+        let fixed = autofix_material(data, target, &opts)?;
+        let material: CompiledMaterialDefinition =
+            fixed.pread_with(0, target).expect("fixed material parses");
+        let (_, code) = material
+            .passes
+            .iter()
+            .flat_map(|(_, pass)| &pass.variants)
+            .flat_map(|variant| &variant.shader_codes)
+            .next()
+            .expect("shader");
+        let shader = BgfxShader::read(&code.bgfx_shader_data).expect("bgfx shader");
+        let start = shader.code.find(b"lightmapUtil_").expect("snippet") + "lightmapUtil_".len();
+        let name = shader.code.get(start..start + "11020_13028".len())?;
+        Some(String::from_utf8_lossy(name).into_owned())
+    }
+
+    #[test]
+    fn lightmaps_are_converted_to_the_game_format() {
+        use MinecraftVersion::*;
+        let v1_21_20 = lightmap_material("1.21.20", V1_21_20);
+        let v1_21_110 = lightmap_material("1.21.110", V1_21_110);
+        let v26_0_24 = lightmap_material("26.0.24", V26_0_24);
+        let cases = [
+            (&v1_21_20, V1_21_110, Some("10023_11020")),
+            (&v1_21_20, V26_0_24, Some("10023_13028")),
+            (&v1_21_110, V26_0_24, Some("11020_13028")),
+            (&v26_0_24, V1_21_110, Some("13028_11020")),
+            (&v1_21_110, V1_21_110, None),
+            (&v26_0_24, V26_0_24, None),
+        ];
+        for (data, target, snippet) in cases {
+            assert_eq!(
+                spliced_snippet(data, target).as_deref(),
+                snippet,
+                "material for {target:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use super::{edit_glsl_shaders, is_modern_profile};
+use crate::materialbin::{CompiledMaterialDefinition, MinecraftVersion};
+use bstr::ByteSlice;
+use memchr::memmem;
+
+/// How the game packs the lightmap uv into a_texcoord1, ids match the snippet names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LightmapEncoding {
+    /// 10023: uv as floats in the 0-1 range
+    Unpacked,
+    /// 11020: uv as the nibbles of an 8 bit value in x
+    Packed8,
+    /// 13028: uv as the nibbles of a 16 bit value in y
+    Packed16,
+}
+
+impl LightmapEncoding {
+    fn of(version: MinecraftVersion) -> Self {
+        match version {
+            MinecraftVersion::V1_18_30
+            | MinecraftVersion::V1_19_60
+            | MinecraftVersion::V1_20_80
+            | MinecraftVersion::V1_21_20 => Self::Unpacked,
+            MinecraftVersion::V1_21_110 => Self::Packed8,
+            MinecraftVersion::V26_0_24 => Self::Packed16,
+        }
+    }
+}
+
+/// Get the snippet that turns what `game` gives into what `shader` expects.
+/// Integer types and bit operations need essl 300 / glsl 130, older shaders get the float math version
+fn conversion_snippet(
+    shader: LightmapEncoding,
+    game: LightmapEncoding,
+    integers: bool,
+) -> Option<&'static str> {
+    use LightmapEncoding::*;
+    let (integer, float) = match (shader, game) {
+        (Unpacked, Packed8) => (
+            include_str!("../../assets/lightmapUtil_10023_11020.glsl"),
+            include_str!("../../assets/lightmapUtil_10023_11020_float.glsl"),
+        ),
+        (Unpacked, Packed16) => (
+            include_str!("../../assets/lightmapUtil_10023_13028.glsl"),
+            include_str!("../../assets/lightmapUtil_10023_13028_float.glsl"),
+        ),
+        (Packed8, Packed16) => (
+            include_str!("../../assets/lightmapUtil_11020_13028.glsl"),
+            include_str!("../../assets/lightmapUtil_11020_13028_float.glsl"),
+        ),
+        (Packed16, Packed8) => (
+            include_str!("../../assets/lightmapUtil_13028_11020.glsl"),
+            include_str!("../../assets/lightmapUtil_13028_11020_float.glsl"),
+        ),
+        _ => return None,
+    };
+    Some(if integers { integer } else { float })
+}
+
+/// Make vertex shaders from `shader_version` read lightmaps the way `game_version` sends them
+pub fn fix_lightmaps(
+    material: &mut CompiledMaterialDefinition,
+    shader_version: MinecraftVersion,
+    game_version: MinecraftVersion,
+) -> usize {
+    let shader = LightmapEncoding::of(shader_version);
+    let game = LightmapEncoding::of(game_version);
+    if shader == game {
+        return 0;
+    }
+    if conversion_snippet(shader, game, false).is_none() {
+        log::warn!("No lightmap conversion from {:?} to {:?}", game, shader);
+        return 0;
+    }
+    let edited = edit_glsl_shaders(material, true, |code| {
+        conversion_snippet(shader, game, is_modern_profile(code))
+            .is_some_and(|snippet| splice_snippet(code, snippet))
+    });
+    log::info!("Fixed lightmaps in {edited} shaders of {}", material.name);
+    edited
+}
+
+/// Insert the snippet after the a_texcoord1 declaration so the macro does not rename it
+fn splice_snippet(code: &mut Vec<u8>, snippet: &str) -> bool {
+    // Already fixed, doing it again would convert twice
+    if memmem::find(code, b"lightmapUtil_").is_some() {
+        return false;
+    }
+    let Some(insert_at) = declaration_end(code) else {
+        return false;
+    };
+    code.splice(insert_at..insert_at, snippet.bytes());
+    true
+}
+
+/// Find the end of the line that declares a_texcoord1
+fn declaration_end(code: &[u8]) -> Option<usize> {
+    for pos in memmem::find_iter(code, b"a_texcoord1") {
+        let line_start = code
+            .get(..pos)
+            .and_then(|before| before.rfind_byte(b'\n'))
+            .map_or(0, |newline| newline + 1);
+        let line_end = code
+            .get(pos..)
+            .and_then(|after| after.find_byte(b'\n'))
+            .map_or(code.len(), |newline| pos + newline + 1);
+        let Some(line) = code.get(line_start..line_end) else {
+            continue;
+        };
+        let line = line.trim_ascii();
+        let is_input = line.starts_with(b"attribute ") || line.starts_with(b"in ");
+        if is_input && line.ends_with(b";") {
+            return Some(line_end);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LightmapEncoding::*;
+
+    const PAIRS: [(LightmapEncoding, LightmapEncoding); 4] = [
+        (Unpacked, Packed8),
+        (Unpacked, Packed16),
+        (Packed8, Packed16),
+        (Packed16, Packed8),
+    ];
+    const ESSL_100: &[u8] = b"attribute vec2 a_texcoord1;\nvoid main() { gl_Position = vec4(a_texcoord1, 0.0, 1.0); }\n";
+    const ESSL_300: &[u8] = b"#version 300 es\nin vec2 a_texcoord1;\nvoid main() { gl_Position = vec4(a_texcoord1, 0.0, 1.0); }\n";
+
+    fn fix(code: &[u8], shader: LightmapEncoding, game: LightmapEncoding) -> Vec<u8> {
+        let mut code = code.to_vec();
+        let snippet = conversion_snippet(shader, game, is_modern_profile(&code)).expect("snippet");
+        assert!(splice_snippet(&mut code, snippet));
+        code
+    }
+
+    fn uses_integers(code: &[u8]) -> bool {
+        [b"uint".as_slice(), b"uvec", b">>", b"<<", b"0x"]
+            .iter()
+            .any(|op| memmem::find(code, op).is_some())
+    }
+
+    #[test]
+    fn essl_100_gets_float_math() {
+        for (shader, game) in PAIRS {
+            let fixed = fix(ESSL_100, shader, game);
+            assert!(!uses_integers(&fixed), "{shader:?} from {game:?}");
+        }
+    }
+
+    #[test]
+    fn essl_300_gets_integer_math() {
+        for (shader, game) in PAIRS {
+            let fixed = fix(ESSL_300, shader, game);
+            assert!(uses_integers(&fixed), "{shader:?} from {game:?}");
+        }
+    }
+
+    #[test]
+    fn snippet_goes_after_the_declaration() {
+        let fixed = fix(ESSL_300, Unpacked, Packed16);
+        let declaration = memmem::find(&fixed, b"in vec2 a_texcoord1;").expect("declaration");
+        let snippet = memmem::find(&fixed, b"lightmapUtil_").expect("snippet");
+        let main = memmem::find(&fixed, b"void main()").expect("main");
+        assert!(declaration < snippet && snippet < main);
+    }
+
+    #[test]
+    fn fixed_shaders_are_left_alone() {
+        let mut fixed = fix(ESSL_100, Unpacked, Packed8);
+        let snippet = conversion_snippet(Unpacked, Packed8, false).expect("snippet");
+        assert!(!splice_snippet(&mut fixed, snippet));
+    }
+
+    #[test]
+    fn shaders_without_the_attribute_are_left_alone() {
+        let mut code = b"attribute vec3 a_position;\nvoid main() {}\n".to_vec();
+        let snippet = conversion_snippet(Unpacked, Packed8, false).expect("snippet");
+        assert!(!splice_snippet(&mut code, snippet));
+    }
+}
@@ -0,0 +1,307 @@
+use super::{edit_glsl_shaders, is_modern_profile};
+use crate::materialbin::CompiledMaterialDefinition;
+use bstr::ByteSlice;
+
+/// Sampler functions that do not exist past essl 100 / glsl 120 and what replaces them
+const RENAMES: [(&[u8], &[u8]); 19] = [
+    (b"texture2DLodEXT", b"textureLod"),
+    (b"texture2DLod", b"textureLod"),
+    (b"texture2DProjLodEXT", b"textureProjLod"),
+    (b"texture2DProjLod", b"textureProjLod"),
+    (b"texture2DGradEXT", b"textureGrad"),
+    (b"texture2DGrad", b"textureGrad"),
+    (b"texture2DProjGradEXT", b"textureProjGrad"),
+    (b"texture2DProjGrad", b"textureProjGrad"),
+    (b"textureCubeLodEXT", b"textureLod"),
+    (b"textureCubeLod", b"textureLod"),
+    (b"textureCubeGradEXT", b"textureGrad"),
+    (b"textureCubeGrad", b"textureGrad"),
+    (b"texture2D", b"texture"),
+    (b"texture2DProj", b"textureProj"),
+    (b"textureCube", b"texture"),
+    (b"texture3D", b"texture"),
+    (b"texture3DProj", b"textureProj"),
+    (b"texture3DLod", b"textureLod"),
+    (b"texture3DProjLod", b"textureProjLod"),
+];
+/// Shadow lookups on a sampler2DShadow gave a vec4, what replaces them gives a float
+const SHADOW_RENAMES: [(&[u8], &[u8]); 4] = [
+    (b"shadow2DEXT", b"texture"),
+    (b"shadow2D", b"texture"),
+    (b"shadow2DProjEXT", b"textureProj"),
+    (b"shadow2DProj", b"textureProj"),
+];
+// Extensions that provide the old functions, newer profiles refuse to compile with them.
+// Their macro stays defined so `#ifdef` blocks around the lookups are still used
+const CORE_EXTENSIONS: [&[u8]; 2] = [b"GL_EXT_shader_texture_lod", b"GL_EXT_shadow_samplers"];
+// Sampler types without a default precision in essl 300, essl 100 extensions gave them one
+const NO_DEFAULT_PRECISION: [&[u8]; 2] = [b"sampler2DShadow", b"sampler3D"];
+
+/// Replace old sampler functions in shaders built for newer profiles
+pub fn fix_texture_lods(material: &mut CompiledMaterialDefinition) -> usize {
+    let edited = edit_glsl_shaders(material, false, rewrite_samplers);
+    if edited != 0 {
+        log::info!(
+            "Fixed texture lookups in {edited} shaders of {}",
+            material.name
+        );
+    }
+    edited
+}
+
+fn rewrite_samplers(code: &mut Vec<u8>) -> bool {
+    if !is_modern_profile(code) {
+        return false;
+    }
+    let mut output = Vec::with_capacity(code.len());
+    let mut changed = false;
+    let mut dropped = Vec::new();
+    for line in code.lines_with_terminator() {
+        let trimmed = line.trim_ascii_start();
+        if trimmed.starts_with(b"#extension") {
+            if let Some(extension) = CORE_EXTENSIONS
+                .into_iter()
+                .find(|extension| line.find(extension).is_some())
+            {
+                if !dropped.contains(&extension) {
+                    dropped.push(extension);
+                }
+                changed = true;
+                continue;
+            }
+        }
+        changed |= rename_calls(line, &mut output);
+    }
+    if !changed {
+        return false;
+    }
+    let mut header = Vec::new();
+    for extension in dropped {
+        header.extend_from_slice(b"#define ");
+        header.extend_from_slice(extension);
+        header.extend_from_slice(b" 1\n");
+    }
+    for sampler in NO_DEFAULT_PRECISION {
+        if has_ident(&output, sampler) && !has_precision_statement(&output, sampler) {
+            header.extend_from_slice(b"precision lowp ");
+            header.extend_from_slice(sampler);
+            header.extend_from_slice(b";\n");
+        }
+    }
+    let header_at = version_line_end(&output);
+    output.splice(header_at..header_at, header);
+    *code = output;
+    true
+}
+
+/// Copy a line to `output` with the old function calls renamed
+fn rename_calls(line: &[u8], output: &mut Vec<u8>) -> bool {
+    let mut changed = false;
+    let mut rest = line;
+    while let Some(start) = rest.iter().position(|c| is_ident(*c)) {
+        let (before, ident_start) = rest.split_at(start);
+        output.extend_from_slice(before);
+        let len = ident_start
+            .iter()
+            .position(|c| !is_ident(*c))
+            .unwrap_or(ident_start.len());
+        let (ident, after) = ident_start.split_at(len);
+        rest = after;
+        let args = after.trim_ascii_start();
+        if !args.starts_with(b"(") {
+            output.extend_from_slice(ident);
+            continue;
+        }
+        if let Some((_, new)) = SHADOW_RENAMES.iter().find(|(old, _)| *old == ident) {
+            // Arguments that go past the line are left as they are, the lookup still gets renamed
+            if let Some(args_len) = call_args_len(args) {
+                let (args, after_call) = after.split_at(after.len() - args.len() + args_len);
+                output.extend_from_slice(b"vec4(");
+                output.extend_from_slice(new);
+                rename_calls(args, output);
+                output.push(b')');
+                rest = after_call;
+            } else {
+                output.extend_from_slice(new);
+            }
+            changed = true;
+            continue;
+        }
+        match RENAMES.iter().find(|(old, _)| *old == ident) {
+            Some((_, new)) => {
+                output.extend_from_slice(new);
+                changed = true;
+            }
+            None => output.extend_from_slice(ident),
+        }
+    }
+    output.extend_from_slice(rest);
+    changed
+}
+
+/// Length of the parenthesized arguments `code` starts with, None if they don't end in it
+fn call_args_len(code: &[u8]) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, c) in code.iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Check that `ident` is used as a whole word somewhere in the code
+fn has_ident(code: &[u8], ident: &[u8]) -> bool {
+    code.find_iter(ident).any(|pos| {
+        let before = pos.checked_sub(1).and_then(|before| code.get(before));
+        let after = code.get(pos + ident.len());
+        !before.is_some_and(|c| is_ident(*c)) && !after.is_some_and(|c| is_ident(*c))
+    })
+}
+
+/// Check for a `precision <qualifier> <sampler>;` statement
+fn has_precision_statement(code: &[u8], sampler: &[u8]) -> bool {
+    code.lines().any(|line| {
+        let mut words = line.trim_ascii().fields();
+        words.next() == Some(b"precision".as_slice())
+            && words
+                .nth(1)
+                .is_some_and(|word| word.trim_end_with(|c| c == ';') == sampler)
+    })
+}
+
+/// Where the line after `#version` starts, nothing else may come before the version
+fn version_line_end(code: &[u8]) -> usize {
+    let mut offset = 0;
+    for line in code.lines_with_terminator() {
+        offset += line.len();
+        if line.trim_ascii_start().starts_with(b"#version") {
+            return offset;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(code: &str) -> Option<String> {
+        let mut code = code.as_bytes().to_vec();
+        rewrite_samplers(&mut code).then(|| String::from_utf8(code).expect("utf8"))
+    }
+
+    #[test]
+    fn old_shaders_are_left_alone() {
+        assert_eq!(
+            rewrite("void main() { gl_FragColor = texture2D(s, uv); }\n"),
+            None
+        );
+        assert_eq!(
+            rewrite("#version 100\nvoid main() { gl_FragColor = texture2D(s, uv); }\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn lookups_are_renamed() {
+        let fixed = rewrite(
+            "#version 300 es\n\
+             vec4 a = texture2DLodEXT(s, uv, 0.0) + texture2DGrad(s, uv, dx, dy);\n\
+             vec4 b = textureCubeGradEXT(c, dir, dx, dy) + texture2DProj(s, p);\n",
+        );
+        assert_eq!(
+            fixed.as_deref(),
+            Some(
+                "#version 300 es\n\
+                 vec4 a = textureLod(s, uv, 0.0) + textureGrad(s, uv, dx, dy);\n\
+                 vec4 b = textureGrad(c, dir, dx, dy) + textureProj(s, p);\n"
+            )
+        );
+    }
+
+    #[test]
+    fn only_calls_are_renamed() {
+        let fixed = rewrite("#version 300 es\nuniform sampler2D texture2D_tex;\nvec4 a = texture2D (texture2D_tex, uv);\n");
+        assert_eq!(
+            fixed.as_deref(),
+            Some("#version 300 es\nuniform sampler2D texture2D_tex;\nvec4 a = texture (texture2D_tex, uv);\n")
+        );
+    }
+
+    #[test]
+    fn shadow_lookups_keep_giving_a_vec4() {
+        let fixed = rewrite(
+            "#version 300 es\n\
+             uniform lowp sampler2DShadow s_Shadow;\n\
+             float a = shadow2DEXT(s_Shadow, vec3(uv, depth)).r;\n",
+        );
+        assert_eq!(
+            fixed.as_deref(),
+            Some(
+                "#version 300 es\n\
+                 precision lowp sampler2DShadow;\n\
+                 uniform lowp sampler2DShadow s_Shadow;\n\
+                 float a = vec4(texture(s_Shadow, vec3(uv, depth))).r;\n"
+            )
+        );
+    }
+
+    #[test]
+    fn precision_statements_are_not_repeated() {
+        let fixed = rewrite(
+            "#version 300 es\n\
+             precision highp sampler2DShadow;\n\
+             uniform sampler2DShadow s_Shadow;\n\
+             uniform samplerCube s_Cube;\n\
+             float a = shadow2D(s_Shadow, p).x + textureCube(s_Cube, dir).x;\n",
+        )
+        .expect("rewritten");
+        assert_eq!(fixed.matches("precision").count(), 1);
+        assert!(!fixed.contains("samplerCube;"));
+    }
+
+    #[test]
+    fn lod_extension_keeps_its_macro() {
+        let fixed = rewrite(
+            "#version 300 es\n\
+             #ifdef GL_EXT_shader_texture_lod\n\
+             #extension GL_EXT_shader_texture_lod : enable\n\
+             #endif\n\
+             #ifdef GL_EXT_shader_texture_lod\n\
+             vec4 a = texture2DLodEXT(s, uv, 0.0);\n\
+             #endif\n",
+        );
+        assert_eq!(
+            fixed.as_deref(),
+            Some(
+                "#version 300 es\n\
+                 #define GL_EXT_shader_texture_lod 1\n\
+                 #ifdef GL_EXT_shader_texture_lod\n\
+                 #endif\n\
+                 #ifdef GL_EXT_shader_texture_lod\n\
+                 vec4 a = textureLod(s, uv, 0.0);\n\
+                 #endif\n"
+            )
+        );
+    }
+
+    #[test]
+    fn modern_shaders_stay_the_same() {
+        assert_eq!(
+            rewrite("#version 310 es\nvec4 a = textureLod(s, uv, 0.0);\n"),
+            None
+        );
+    }
+}
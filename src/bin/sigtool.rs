@@ -0,0 +1,271 @@
+// Makes a signature for a new game build from an older build where the constructor address is known.
+// Runs on the host, not on Android:
+//     sigtool <old libminecraftpe.so> <constructor address> <new libminecraftpe.so> [versions]
+// The signature line is printed to stdout, ready for signatures.txt
+use mtbinloader2::{
+    decode::Arch,
+    elf::ElfInfo,
+    pattern::BytePattern,
+    signatures::{self, SignatureDb},
+};
+use std::{env, fs, io::Cursor, process::ExitCode};
+
+// Shorter patterns match all over the place anyway
+const MIN_LEN: usize = 8;
+// A function that stays the same for this long is unique, or the pattern would not help
+const MAX_LEN: usize = 256;
+
+struct Library {
+    data: Vec<u8>,
+    elf: ElfInfo,
+    arch: Arch,
+}
+
+impl Library {
+    fn load(path: &str) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+        let elf = ElfInfo::read(&mut Cursor::new(&data))
+            .map_err(|e| format!("Cannot parse {path}: {e}"))?;
+        let arch = Arch::from_elf_machine(elf.machine)
+            .ok_or(format!("{path} has an unknown machine {}", elf.machine))?;
+        Ok(Self { data, elf, arch })
+    }
+    /// .text and its address
+    fn text(&self) -> Result<(u64, &[u8]), String> {
+        let text = self.elf.text.clone().ok_or("The library has no .text")?;
+        let bytes = self.bytes_at(text.start)?;
+        let len = usize::try_from(text.end - text.start).map_err(|e| e.to_string())?;
+        Ok((text.start, bytes.get(..len).ok_or(".text is cut off")?))
+    }
+    /// Everything in the file from the bytes loaded at `vaddr`
+    fn bytes_at(&self, vaddr: u64) -> Result<&[u8], String> {
+        self.elf
+            .vaddr_to_offset(vaddr)
+            .and_then(|offset| self.data.get(usize::try_from(offset).ok()?..))
+            .ok_or(format!("{vaddr:x} is not in the file"))
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [old, address, new, rest @ ..] = args.as_slice() else {
+        eprintln!(
+            "Usage: sigtool <old libminecraftpe.so> <constructor address> <new libminecraftpe.so> [versions]"
+        );
+        return ExitCode::FAILURE;
+    };
+    let versions = rest.first().map_or("*", String::as_str);
+    match run(old, address, new, versions) {
+        Ok(line) => {
+            println!("{line}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(old: &str, address: &str, new: &str, versions: &str) -> Result<String, String> {
+    let old = Library::load(old)?;
+    let new = Library::load(new)?;
+    if old.arch != new.arch {
+        return Err(format!("Cannot compare {} with {}", old.arch, new.arch));
+    }
+    let arch = old.arch;
+    let address = u64::from_str_radix(address.trim_start_matches("0x"), 16)
+        .map_err(|e| format!("Invalid address {address}: {e}"))?;
+    // Thumb function addresses have the lowest bit set
+    let address = match arch {
+        Arch::Arm => address & !1,
+        _ => address,
+    };
+    let (old_text_start, old_text) = old.text()?;
+    let old_text_end = old_text_start + old_text.len() as u64;
+    if !(old_text_start..old_text_end).contains(&address) {
+        return Err(format!(
+            "{address:x} is outside of .text in the old library"
+        ));
+    }
+    let available = usize::try_from(old_text_end - address).map_err(|e| e.to_string())?;
+    let window = old
+        .bytes_at(address)?
+        .get(..available.min(MAX_LEN))
+        .ok_or("The constructor is cut off")?;
+    let masked = mask(arch, window);
+
+    let (new_text_start, new_text) = new.text()?;
+    let step = match arch {
+        Arch::Aarch64 => 4,
+        Arch::Arm => 2,
+        Arch::X86_64 | Arch::X86 => 1,
+    };
+    for len in (MIN_LEN..=masked.len()).step_by(step) {
+        let text = pattern_text(masked.get(..len).unwrap_or_default());
+        // Nothing but relocations so far
+        let Ok(pattern) = BytePattern::parse(&text) else {
+            continue;
+        };
+        match pattern.find_all(new_text).as_slice() {
+            [] => {
+                return Err(format!(
+                    "The constructor changed within its first {len} bytes, nothing in the new build matches"
+                ))
+            }
+            [found] => {
+                let found = new_text_start + *found as u64;
+                let line = format!("{arch} {versions} 0 {text}");
+                check_line(&line, new_text_start, new_text, found)?;
+                eprintln!(
+                    "{len} bytes, {} fixed, point to {found:x} in the new build",
+                    pattern.specificity()
+                );
+                return Ok(line);
+            }
+            _ => {}
+        }
+    }
+    Err(format!(
+        "No pattern of up to {} bytes is unique in the new build",
+        masked.len()
+    ))
+}
+
+/// Make sure the loader reads the line the same way we meant it
+fn check_line(line: &str, base: u64, text: &[u8], expected: u64) -> Result<(), String> {
+    let file = format!(
+        "{} {}\n{line}",
+        signatures::HEADER,
+        signatures::FORMAT_VERSION
+    );
+    let (db, errors) = SignatureDb::parse(&file);
+    if let Some(error) = errors.first() {
+        return Err(format!("Generated an invalid signature, {error}"));
+    }
+    let signatures: Vec<_> = db.entries.iter().collect();
+    let base = usize::try_from(base).map_err(|e| e.to_string())?;
+    let report = signatures::scan(&signatures, &[(base, text)]);
+    match report.best() {
+        Some((_, target)) if target as u64 == expected => Ok(()),
+        _ => Err("The generated signature does not point to the match".to_owned()),
+    }
+}
+
+fn pattern_text(bytes: &[Option<u8>]) -> String {
+    bytes
+        .iter()
+        .map(|byte| match byte {
+            Some(byte) => format!("{byte:02X}"),
+            None => "??".to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Wildcard the bytes that change when code or data moves between builds:
+/// branch offsets, PC relative addresses and the page offsets that go with them
+fn mask(arch: Arch, code: &[u8]) -> Vec<Option<u8>> {
+    let mut masked: Vec<Option<u8>> = code.iter().copied().map(Some).collect();
+    let mut wildcard = |range: std::ops::Range<usize>| {
+        for byte in masked.get_mut(range).into_iter().flatten() {
+            *byte = None;
+        }
+    };
+    match arch {
+        Arch::Aarch64 => {
+            let mut after_adrp = false;
+            for (index, insn) in code.chunks_exact(4).enumerate() {
+                let at = index * 4;
+                let Ok(insn) = insn.try_into().map(u32::from_le_bytes) else {
+                    continue;
+                };
+                let adrp = insn & 0x9F00_0000 == 0x9000_0000;
+                // B, BL, ADRP and ADR
+                if insn & 0x7C00_0000 == 0x1400_0000 || insn & 0x1F00_0000 == 0x1000_0000 {
+                    wildcard(at..at + 4);
+                // B.cond, CBZ/CBNZ, TBZ/TBNZ and LDR literal keep their offset in the low 3 bytes
+                } else if insn & 0xFF00_0010 == 0x5400_0000
+                    || insn & 0x7C00_0000 == 0x3400_0000
+                    || insn & 0x3B00_0000 == 0x1800_0000
+                {
+                    wildcard(at..at + 3);
+                // The ADD or LDR/STR that finishes an ADRP holds the page offset
+                } else if after_adrp
+                    && (insn & 0x7F80_0000 == 0x1100_0000 || insn & 0x3B00_0000 == 0x3900_0000)
+                {
+                    wildcard(at + 1..at + 3);
+                }
+                after_adrp = adrp;
+            }
+        }
+        Arch::Arm => {
+            let mut at = 0;
+            while let Some(&[low, high]) = code.get(at..at + 2) {
+                let first = u16::from_le_bytes([low, high]);
+                // Thumb-2 instructions are two halfwords
+                if matches!(first >> 11, 0x1D..=0x1F) {
+                    let second = code
+                        .get(at + 2..at + 4)
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .map_or(0, u16::from_le_bytes);
+                    // BL, BLX, B.W, and MOVW/MOVT which build addresses
+                    if (first & 0xF800 == 0xF000 && second & 0x8000 == 0x8000)
+                        || first & 0xFB70 == 0xF240
+                    {
+                        wildcard(at..at + 4);
+                    // LDR.W literal
+                    } else if first & 0xFF7F == 0xF85F {
+                        wildcard(at + 2..at + 4);
+                    }
+                    at += 4;
+                    continue;
+                }
+                // LDR literal, B<cond>
+                if first & 0xF800 == 0x4800
+                    || (first & 0xF000 == 0xD000 && first & 0x0E00 != 0x0E00)
+                {
+                    wildcard(at..at + 1);
+                // B, CBZ/CBNZ
+                } else if first & 0xF800 == 0xE000 || first & 0xF500 == 0xB100 {
+                    wildcard(at..at + 2);
+                }
+                at += 2;
+            }
+        }
+        Arch::X86_64 | Arch::X86 => {
+            // No real decoding, anything that looks like a rel32 gets masked, which at worst costs a few fixed bytes
+            let mut at = 0;
+            while let Some(&opcode) = code.get(at) {
+                let next = code.get(at + 1).copied().unwrap_or_default();
+                let disp = match opcode {
+                    // CALL, JMP
+                    0xE8 | 0xE9 => Some(at + 1),
+                    // Jcc rel32
+                    0x0F if next & 0xF0 == 0x80 => Some(at + 2),
+                    // MOV, LEA, CMP and friends with a RIP relative operand, after an optional REX
+                    _ => {
+                        let (opcode_at, opcode) =
+                            match arch == Arch::X86_64 && opcode & 0xF0 == 0x40 {
+                                true => (at + 1, next),
+                                false => (at, opcode),
+                            };
+                        let modrm = code.get(opcode_at + 1).copied().unwrap_or_default();
+                        let rip_relative =
+                            matches!(opcode, 0x8B | 0x8D | 0x89 | 0x3B | 0x39 | 0xFF)
+                                && modrm & 0xC7 == 0x05;
+                        (arch == Arch::X86_64 && rip_relative).then_some(opcode_at + 2)
+                    }
+                };
+                match disp {
+                    Some(disp) => {
+                        wildcard(disp..disp + 4);
+                        at = disp + 4;
+                    }
+                    None => at += 1,
+                }
+            }
+        }
+    }
+    masked
+}
@@ -0,0 +1,109 @@
+// Keeps loaded files around since the game opens the same ones over and over
+use crate::loader::Buffer;
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+const MAX_ENTRIES: usize = 32;
+const MAX_BYTES: usize = 64 * 1024 * 1024;
+const MAX_MISSING: usize = 4096;
+// How often the counters get logged
+const LOG_INTERVAL: usize = 512;
+
+/// LRU cache of closed buffers plus a set of paths no source has
+#[derive(Default)]
+pub struct BufferCache {
+    // Most recently used first
+    entries: VecDeque<Buffer>,
+    bytes: usize,
+    missing: HashSet<PathBuf>,
+    generation: (usize, usize),
+    hits: usize,
+    misses: usize,
+    missing_hits: usize,
+}
+
+impl BufferCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Drop everything if the packs or sources changed since the last call
+    pub fn sync(&mut self, generation: (usize, usize)) {
+        if self.generation == generation {
+            return;
+        }
+        if !self.entries.is_empty() || !self.missing.is_empty() {
+            log::info!("Clearing file cache");
+            self.log_stats();
+        }
+        self.entries.clear();
+        self.missing.clear();
+        self.bytes = 0;
+        self.generation = generation;
+    }
+    /// Take a cached buffer out, it comes back when the asset is closed
+    pub fn take(&mut self, path: &Path) -> Option<Buffer> {
+        self.count_lookup();
+        let index = self.entries.iter().position(|buffer| buffer.name == path);
+        let Some(buffer) = index.and_then(|index| self.entries.remove(index)) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        self.bytes -= buffer.get_ref().len();
+        Some(buffer)
+    }
+    /// Check if a path is known to not be in any source
+    pub fn is_missing(&mut self, path: &Path) -> bool {
+        let missing = self.missing.contains(path);
+        if missing {
+            self.missing_hits += 1;
+        }
+        missing
+    }
+    pub fn mark_missing(&mut self, path: &Path) {
+        // Forgetting everything is cheaper than tracking the oldest entry
+        if self.missing.len() >= MAX_MISSING {
+            self.missing.clear();
+        }
+        self.missing.insert(path.to_path_buf());
+    }
+    /// Put a buffer back after its asset got closed
+    pub fn insert(&mut self, buffer: Buffer) {
+        let len = buffer.get_ref().len();
+        if !buffer.cacheable || len > MAX_BYTES {
+            return;
+        }
+        // Another asset with the same file might have been closed already
+        if let Some(index) = self.entries.iter().position(|b| b.name == buffer.name) {
+            if let Some(old) = self.entries.remove(index) {
+                self.bytes -= old.get_ref().len();
+            }
+        }
+        while self.entries.len() >= MAX_ENTRIES || self.bytes + len > MAX_BYTES {
+            let Some(old) = self.entries.pop_back() else {
+                break;
+            };
+            self.bytes -= old.get_ref().len();
+        }
+        self.bytes += len;
+        self.entries.push_front(buffer);
+    }
+    fn count_lookup(&self) {
+        let lookups = self.hits + self.misses;
+        if lookups != 0 && lookups.is_multiple_of(LOG_INTERVAL) {
+            self.log_stats();
+        }
+    }
+    fn log_stats(&self) {
+        log::info!(
+            "File cache: {} hits, {} misses, {} known missing hits, {} entries ({} bytes)",
+            self.hits,
+            self.misses,
+            self.missing_hits,
+            self.entries.len(),
+            self.bytes
+        );
+    }
+}
@@ -0,0 +1,281 @@
+// Just enough instruction decoding to follow calls and address references found by signatures
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Arch {
+    Aarch64,
+    /// Thumb-2, the game is not built as ARM code
+    Arm,
+    X86_64,
+    X86,
+}
+
+impl Arch {
+    /// Same names as `std::env::consts::ARCH`
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Aarch64 => "aarch64",
+            Self::Arm => "arm",
+            Self::X86_64 => "x86_64",
+            Self::X86 => "x86",
+        }
+    }
+    /// The architecture of an ELF e_machine value
+    pub const fn from_elf_machine(machine: u16) -> Option<Self> {
+        match machine {
+            183 => Some(Self::Aarch64),
+            40 => Some(Self::Arm),
+            62 => Some(Self::X86_64),
+            3 => Some(Self::X86),
+            _ => None,
+        }
+    }
+    /// Where compilers start functions, a target off this can't be a function
+    pub const fn function_alignment(self) -> usize {
+        match self {
+            Self::Aarch64 => 4,
+            Self::Arm => 2,
+            // Clang pads x86 functions to 16 bytes unless optimizing for size
+            Self::X86_64 | Self::X86 => 16,
+        }
+    }
+}
+
+impl FromStr for Arch {
+    type Err = ();
+    fn from_str(name: &str) -> Result<Self, ()> {
+        let arch = match name {
+            "aarch64" => Self::Aarch64,
+            "arm" => Self::Arm,
+            "x86_64" => Self::X86_64,
+            "x86" => Self::X86,
+            _ => return Err(()),
+        };
+        Ok(arch)
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Target of the call or jump at the start of `code`, which is at address `pc`.
+/// Handles aarch64 BL/B, Thumb-2 BL/B.W and x86 CALL/JMP rel32
+pub fn branch_target(arch: Arch, code: &[u8], pc: usize) -> Option<usize> {
+    match arch {
+        Arch::Aarch64 => {
+            let insn = read_u32(code)?;
+            // BL or B
+            if insn & 0x7C00_0000 != 0x1400_0000 {
+                return None;
+            }
+            let offset = sign_extend(insn & 0x03FF_FFFF, 26) << 2;
+            pc.checked_add_signed(offset)
+        }
+        Arch::Arm => {
+            let first = u32::from(read_u16(code)?);
+            let second = u32::from(read_u16(code.get(2..)?)?);
+            // BL or B.W, BLX would switch to ARM code which the game does not have
+            if first & 0xF800 != 0xF000 || !matches!(second & 0xD000, 0xD000 | 0x9000) {
+                return None;
+            }
+            let s = (first >> 10) & 1;
+            let i1 = !((second >> 13) ^ s) & 1;
+            let i2 = !((second >> 11) ^ s) & 1;
+            let imm = (s << 24)
+                | (i1 << 23)
+                | (i2 << 22)
+                | ((first & 0x3FF) << 12)
+                | ((second & 0x7FF) << 1);
+            // The pc is 4 bytes ahead in Thumb mode
+            pc.checked_add(4)?.checked_add_signed(sign_extend(imm, 25))
+        }
+        Arch::X86_64 | Arch::X86 => {
+            let (opcode, rest) = code.split_first()?;
+            // CALL or JMP rel32
+            if !matches!(opcode, 0xE8 | 0xE9) {
+                return None;
+            }
+            let offset = read_i32(rest)?;
+            pc.checked_add(5)?.checked_add_signed(offset as isize)
+        }
+    }
+}
+
+/// Address loaded by the instruction(s) at the start of `code`, which is at address `pc`.
+/// Handles aarch64 ADRP+ADD and x86_64 RIP-relative LEA.
+/// Thumb code gets addresses from a literal pool LDR and an ADD of the pc some instructions later,
+/// with nothing tying the two together, so arm (and x86, which has no pc relative addressing) has none
+pub fn reference_target(arch: Arch, code: &[u8], pc: usize) -> Option<usize> {
+    match arch {
+        Arch::Aarch64 => {
+            let adrp = read_u32(code)?;
+            let add = read_u32(code.get(4..)?)?;
+            if adrp & 0x9F00_0000 != 0x9000_0000 {
+                return None;
+            }
+            // 64 bit ADD immediate, reading the register ADRP wrote
+            if add & 0xFF80_0000 != 0x9100_0000 || (add >> 5) & 0x1F != adrp & 0x1F {
+                return None;
+            }
+            let immlo = (adrp >> 29) & 0x3;
+            let immhi = (adrp >> 5) & 0x7_FFFF;
+            let page_offset = sign_extend((immhi << 2) | immlo, 21) << 12;
+            let page = (pc & !0xFFF).checked_add_signed(page_offset)?;
+            let shift = if add & (1 << 22) != 0 { 12 } else { 0 };
+            let imm = ((add >> 10) & 0xFFF) << shift;
+            page.checked_add(imm as usize)
+        }
+        Arch::X86_64 => {
+            // An optional REX prefix, LEA, then a ModRM with mod 00 and rm 101 for RIP-relative
+            let (rex_len, code) = match code.first()? {
+                rex if rex & 0xF0 == 0x40 => (1, code.get(1..)?),
+                _ => (0, code),
+            };
+            let [0x8D, modrm, ref disp @ ..] = *code else {
+                return None;
+            };
+            if modrm & 0xC7 != 0x05 {
+                return None;
+            }
+            let disp = read_i32(disp)?;
+            // Opcode, ModRM and the displacement
+            let len = rex_len + 6;
+            pc.checked_add(len)?.checked_add_signed(disp as isize)
+        }
+        Arch::Arm | Arch::X86 => None,
+    }
+}
+
+fn sign_extend(value: u32, bits: u32) -> isize {
+    let shift = 32 - bits;
+    (((value << shift) as i32) >> shift) as isize
+}
+
+fn read_u16(code: &[u8]) -> Option<u16> {
+    Some(u16::from_le_bytes(code.get(..2)?.try_into().ok()?))
+}
+
+fn read_u32(code: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(code.get(..4)?.try_into().ok()?))
+}
+
+fn read_i32(code: &[u8]) -> Option<i32> {
+    Some(i32::from_le_bytes(code.get(..4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aarch64_branches() {
+        // bl +0x100
+        assert_eq!(
+            branch_target(Arch::Aarch64, &[0x40, 0x00, 0x00, 0x94], 0x1000),
+            Some(0x1100)
+        );
+        // b -8
+        assert_eq!(
+            branch_target(Arch::Aarch64, &[0xFE, 0xFF, 0xFF, 0x17], 0x1000),
+            Some(0xFF8)
+        );
+        // nop
+        assert_eq!(
+            branch_target(Arch::Aarch64, &[0x1F, 0x20, 0x03, 0xD5], 0x1000),
+            None
+        );
+        assert_eq!(
+            branch_target(Arch::Aarch64, &[0x40, 0x00, 0x00], 0x1000),
+            None
+        );
+    }
+
+    #[test]
+    fn thumb_branches() {
+        // bl +0x1234
+        assert_eq!(
+            branch_target(Arch::Arm, &[0x01, 0xF0, 0x1A, 0xF9], 0x1000),
+            Some(0x1004 + 0x1234)
+        );
+        // bl -0x10
+        assert_eq!(
+            branch_target(Arch::Arm, &[0xFF, 0xF7, 0xF8, 0xFF], 0x1000),
+            Some(0x1004 - 0x10)
+        );
+        // b.w +0x200000
+        assert_eq!(
+            branch_target(Arch::Arm, &[0x00, 0xF2, 0x00, 0xB8], 0x1000),
+            Some(0x1004 + 0x20_0000)
+        );
+        // blx +0x1234, into ARM code
+        assert_eq!(
+            branch_target(Arch::Arm, &[0x01, 0xF0, 0x1A, 0xE9], 0x1000),
+            None
+        );
+        // push {r4-r7, lr}; add r7, sp, #12
+        assert_eq!(
+            branch_target(Arch::Arm, &[0xF0, 0xB5, 0x03, 0xAF], 0x1000),
+            None
+        );
+    }
+
+    #[test]
+    fn x86_branches() {
+        // call +0x10
+        let call = [0xE8, 0x10, 0x00, 0x00, 0x00];
+        assert_eq!(branch_target(Arch::X86_64, &call, 0x1000), Some(0x1015));
+        assert_eq!(branch_target(Arch::X86, &call, 0x1000), Some(0x1015));
+        // jmp -0x20
+        assert_eq!(
+            branch_target(Arch::X86_64, &[0xE9, 0xE0, 0xFF, 0xFF, 0xFF], 0x1000),
+            Some(0xFE5)
+        );
+        // push rbp
+        assert_eq!(
+            branch_target(Arch::X86_64, &[0x55, 0, 0, 0, 0], 0x1000),
+            None
+        );
+    }
+
+    #[test]
+    fn aarch64_references() {
+        // adrp x8, +3 pages; add x8, x8, #0x9a0
+        let code = [0x08, 0x00, 0x00, 0xF0, 0x08, 0x81, 0x26, 0x91];
+        assert_eq!(
+            reference_target(Arch::Aarch64, &code, 0x1234),
+            Some(0x4000 + 0x9A0)
+        );
+        // adrp x8, -2 pages; add x9, x9, #0x10 reads another register
+        let code = [0xE8, 0xFF, 0xFF, 0xD0, 0x29, 0x41, 0x00, 0x91];
+        assert_eq!(reference_target(Arch::Aarch64, &code, 0x5678), None);
+        // adrp x8, -2 pages; add x8, x8, #0x10
+        let code = [0xE8, 0xFF, 0xFF, 0xD0, 0x08, 0x41, 0x00, 0x91];
+        assert_eq!(
+            reference_target(Arch::Aarch64, &code, 0x5678),
+            Some(0x3000 + 0x10)
+        );
+    }
+
+    #[test]
+    fn x86_64_references() {
+        // lea rdi, [rip + 0x100]
+        let code = [0x48, 0x8D, 0x3D, 0x00, 0x01, 0x00, 0x00];
+        assert_eq!(reference_target(Arch::X86_64, &code, 0x1000), Some(0x1107));
+        // lea eax, [rip - 0x10]
+        let code = [0x8D, 0x05, 0xF0, 0xFF, 0xFF, 0xFF];
+        assert_eq!(reference_target(Arch::X86_64, &code, 0x1000), Some(0xFF6));
+        // lea rdi, [rbx + 8]
+        let code = [0x48, 0x8D, 0x7B, 0x08, 0x00, 0x00, 0x00];
+        assert_eq!(reference_target(Arch::X86_64, &code, 0x1000), None);
+    }
+
+    #[test]
+    fn arm_has_no_references() {
+        // ldr r0, [pc, #8]; add r0, pc
+        let code = [0x02, 0x48, 0x78, 0x44];
+        assert_eq!(reference_target(Arch::Arm, &code, 0x1000), None);
+    }
+}
@@ -0,0 +1,126 @@
+// Finding out which game version we are running in, before anything gets hooked
+use crate::{plthook::find_export, version::GameVersion};
+use jni::{
+    objects::{JObject, JString},
+    sys, JNIEnv, JavaVM,
+};
+use std::sync::OnceLock;
+
+#[cfg_attr(test, allow(dead_code))]
+const GAME_PACKAGE: &str = "com.mojang.minecraftpe";
+static GAME_VERSION: OnceLock<GameVersion> = OnceLock::new();
+
+/// The version found by [`init`], if any
+pub fn game_version() -> Option<GameVersion> {
+    GAME_VERSION.get().copied()
+}
+
+/// Detect the game version, from the installed game package if Java is reachable
+/// and from the strings in `data` (the readable parts of the library) otherwise
+#[cfg_attr(test, allow(dead_code))]
+pub fn init(data: &[&[u8]]) -> Option<GameVersion> {
+    let found = from_package()
+        .map(|version| (version, "package info"))
+        .or_else(|| from_strings(data).map(|version| (version, "library strings")));
+    let Some((version, source)) = found else {
+        log::warn!("Could not find out the game version");
+        return None;
+    };
+    log::info!("Running in Minecraft {version}, found from the {source}");
+    let _ = GAME_VERSION.set(version);
+    Some(version)
+}
+
+#[cfg_attr(test, allow(dead_code))]
+type GetCreatedJavaVms =
+    unsafe extern "C" fn(*mut *mut sys::JavaVM, sys::jsize, *mut sys::jsize) -> sys::jint;
+
+/// The versionName of the game package, read through the VM that loaded us
+#[cfg_attr(test, allow(dead_code))]
+fn from_package() -> Option<GameVersion> {
+    // Exported by libnativehelper since Android 12, only libart has it before that
+    let get_vms = find_export(c"libnativehelper.so", "JNI_GetCreatedJavaVMs")
+        .or_else(|| find_export(c"libart.so", "JNI_GetCreatedJavaVMs"))?;
+    let get_vms: GetCreatedJavaVms = unsafe { std::mem::transmute(get_vms) };
+    let mut vm = std::ptr::null_mut();
+    let mut count = 0;
+    if unsafe { get_vms(&mut vm, 1, &mut count) } != sys::JNI_OK || count < 1 {
+        return None;
+    }
+    let vm = unsafe { JavaVM::from_raw(vm) }.ok()?;
+    // Only works when System.loadLibrary loaded us, threads are never attached just for this
+    let mut env = vm.get_env().ok()?;
+    let name = match version_name(&mut env) {
+        Ok(name) => name,
+        Err(e) => {
+            // A pending exception would be thrown at whoever loaded us
+            if env.exception_check().unwrap_or(false) {
+                let _ = env.exception_clear();
+            }
+            log::warn!("Cannot read the {GAME_PACKAGE} version: {e}");
+            return None;
+        }
+    };
+    // Betas and previews can have a suffix after the numbers
+    let numbers = name
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?;
+    numbers.trim_end_matches('.').parse().ok()
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn version_name(env: &mut JNIEnv) -> jni::errors::Result<String> {
+    let app = env
+        .call_static_method(
+            "android/app/ActivityThread",
+            "currentApplication",
+            "()Landroid/app/Application;",
+            &[],
+        )?
+        .l()?;
+    let manager = env
+        .call_method(
+            &app,
+            "getPackageManager",
+            "()Landroid/content/pm/PackageManager;",
+            &[],
+        )?
+        .l()?;
+    let package = JObject::from(env.new_string(GAME_PACKAGE)?);
+    let info = env
+        .call_method(
+            &manager,
+            "getPackageInfo",
+            "(Ljava/lang/String;I)Landroid/content/pm/PackageInfo;",
+            &[(&package).into(), 0.into()],
+        )?
+        .l()?;
+    let name = JString::from(
+        env.get_field(&info, "versionName", "Ljava/lang/String;")?
+            .l()?,
+    );
+    let name = env.get_string(&name)?.into();
+    Ok(name)
+}
+
+/// The highest full release number (like 1.21.120.4) stored as its own string.
+/// Versions the game checks against are written with fewer parts, so they are not picked up
+#[cfg_attr(test, allow(dead_code))]
+pub fn from_strings(data: &[&[u8]]) -> Option<GameVersion> {
+    data.iter()
+        .flat_map(|region| region.split(|byte| *byte == 0))
+        .filter(|string| (7..=16).contains(&string.len()))
+        .filter(|string| string.first().is_some_and(u8::is_ascii_digit))
+        .filter(|string| {
+            string
+                .iter()
+                .all(|byte| byte.is_ascii_digit() || *byte == b'.')
+        })
+        .filter_map(|string| std::str::from_utf8(string).ok())
+        .filter_map(GameVersion::parse_with_len)
+        .filter(|(_, len)| *len == 4)
+        .map(|(version, _)| version)
+        // Old releases are all 1.x, the yearly scheme starts at 26
+        .filter(|version| version.major() == 1 || version.major() >= 26)
+        .max()
+}
@@ -0,0 +1,15 @@
+// The linker picks the namespace of a dlopen from the address it was called from.
+// Rust cannot read its own return address, so the PLT entries point here
+// and the library that called dlopen is passed on to dlopen.rs
+extern "C" {
+  void* mbl2_dlopen(const char* filename, int flags, const void* caller);
+  void* mbl2_android_dlopen_ext(const char* filename, int flags, const void* extinfo,
+                                const void* caller);
+
+  void* mbl2_dlopen_hook(const char* filename, int flags) {
+    return mbl2_dlopen(filename, flags, __builtin_return_address(0));
+  }
+  void* mbl2_android_dlopen_ext_hook(const char* filename, int flags, const void* extinfo) {
+    return mbl2_android_dlopen_ext(filename, flags, extinfo, __builtin_return_address(0));
+  }
+}
@@ -0,0 +1,158 @@
+// Libraries loaded after startup never went through hook_aaset,
+// so we watch dlopen calls and hook the new ones as they come in
+use crate::{
+    plthook::{find_export, replace_plt_functions, PltHook},
+    LockResultExt,
+};
+use libc::{c_char, c_int, c_void};
+use plt_rs::DynamicLibrary;
+use std::{
+    ffi::CStr,
+    ptr,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        LazyLock, Mutex,
+    },
+};
+
+type DlopenFn = unsafe extern "C" fn(*const c_char, c_int) -> *mut c_void;
+// The extinfo struct is only passed along, so it stays opaque
+type DlopenExtFn = unsafe extern "C" fn(*const c_char, c_int, *const c_void) -> *mut c_void;
+// What libdl forwards to since Android 8, with the caller address as the last argument
+type LoaderDlopenFn = unsafe extern "C" fn(*const c_char, c_int, *const c_void) -> *mut c_void;
+type LoaderDlopenExtFn =
+    unsafe extern "C" fn(*const c_char, c_int, *const c_void, *const c_void) -> *mut c_void;
+
+// Every library imports these from the linker, so any saved original will do
+static ORIGINAL_DLOPEN: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+static ORIGINAL_DLOPEN_EXT: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+static HOOKS: Mutex<Vec<(String, PltHook)>> = Mutex::new(Vec::new());
+static LOADER_DLOPEN: LazyLock<Option<LoaderDlopenFn>> = LazyLock::new(|| unsafe {
+    find_export(c"libdl.so", "__loader_dlopen").map(|function| std::mem::transmute(function))
+});
+static LOADER_DLOPEN_EXT: LazyLock<Option<LoaderDlopenExtFn>> = LazyLock::new(|| unsafe {
+    find_export(c"libdl.so", "__loader_android_dlopen_ext")
+        .map(|function| std::mem::transmute(function))
+});
+// The libdl exports themselves, which only pass the call on to the __loader functions
+static LIBDL_DLOPEN: LazyLock<Option<usize>> =
+    LazyLock::new(|| find_export(c"libdl.so", "dlopen").map(|function| function as usize));
+static LIBDL_DLOPEN_EXT: LazyLock<Option<usize>> = LazyLock::new(|| {
+    find_export(c"libdl.so", "android_dlopen_ext").map(|function| function as usize)
+});
+
+// In dlopen.cpp, they call the functions below with their return address added
+extern "C" {
+    fn mbl2_dlopen_hook(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn mbl2_android_dlopen_ext_hook(
+        filename: *const c_char,
+        flags: c_int,
+        extinfo: *const c_void,
+    ) -> *mut c_void;
+}
+
+/// Libraries that load others on behalf of the app, System.loadLibrary goes through libnativeloader
+pub const LOADER_LIBRARIES: [&str; 1] = ["libnativeloader.so"];
+
+/// Watch the dlopen calls of a library, returns whether any of them go through us now
+pub fn hook_dlopen_in(dyn_lib: &DynamicLibrary, name: &str) -> bool {
+    let functions = [
+        ("dlopen", mbl2_dlopen_hook as *const u8),
+        (
+            "android_dlopen_ext",
+            mbl2_android_dlopen_ext_hook as *const u8,
+        ),
+    ];
+    let report = replace_plt_functions(dyn_lib, functions, &[]);
+    let hooked = ["dlopen", "android_dlopen_ext"]
+        .iter()
+        .any(|symbol| report.is_hooked(symbol));
+    let mut hooks = HOOKS.lock().ignore_poison();
+    for (symbol, hook) in report.into_hooks() {
+        let original = unsafe { hook.original::<*mut c_void>() };
+        let slot = match symbol {
+            "dlopen" => &ORIGINAL_DLOPEN,
+            _ => &ORIGINAL_DLOPEN_EXT,
+        };
+        // Keep the first one, another mod might sit in front of the linker in some libraries only
+        let _ = slot.compare_exchange(
+            ptr::null_mut(),
+            original,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+        log::info!("Watching {symbol} calls in {name}");
+        hooks.push((name.to_owned(), hook));
+    }
+    hooked
+}
+
+/// Stop watching dlopen calls
+pub fn unhook_dlopen() {
+    let hooks = std::mem::take(&mut *HOOKS.lock().ignore_poison());
+    for (library, hook) in hooks {
+        if let Err(e) = hook.uninstall() {
+            log::error!("Failed to unhook dlopen in {library}: {e}");
+        }
+    }
+}
+
+/// Get a saved original, or look the real one up if a call raced the hook install
+unsafe fn original(saved: &AtomicPtr<c_void>, symbol: &CStr) -> Option<*mut c_void> {
+    let mut function = saved.load(Ordering::Acquire);
+    if function.is_null() {
+        function = libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr());
+    }
+    (!function.is_null()).then_some(function)
+}
+
+// Calls always go to the saved original so hooks other mods put on dlopen keep working.
+// Only when that is the libdl export is it skipped for the __loader function it forwards to,
+// the linker picks the namespace from the caller address and that way gets the library that called us.
+// Android 7 and older have no __loader functions, there the original runs with us as the caller,
+// we live in the app namespace like the game does and libnativeloader passes its namespace explicitly
+#[no_mangle]
+unsafe extern "C" fn mbl2_dlopen(
+    filename: *const c_char,
+    flags: c_int,
+    caller: *const c_void,
+) -> *mut c_void {
+    let Some(original) = original(&ORIGINAL_DLOPEN, c"dlopen") else {
+        return ptr::null_mut();
+    };
+    let handle = match *LOADER_DLOPEN {
+        Some(loader_dlopen) if *LIBDL_DLOPEN == Some(original as usize) => {
+            loader_dlopen(filename, flags, caller)
+        }
+        _ => std::mem::transmute::<*mut c_void, DlopenFn>(original)(filename, flags),
+    };
+    after_dlopen(handle, filename);
+    handle
+}
+
+#[no_mangle]
+unsafe extern "C" fn mbl2_android_dlopen_ext(
+    filename: *const c_char,
+    flags: c_int,
+    extinfo: *const c_void,
+    caller: *const c_void,
+) -> *mut c_void {
+    let Some(original) = original(&ORIGINAL_DLOPEN_EXT, c"android_dlopen_ext") else {
+        return ptr::null_mut();
+    };
+    let handle = match *LOADER_DLOPEN_EXT {
+        Some(loader_dlopen_ext) if *LIBDL_DLOPEN_EXT == Some(original as usize) => {
+            loader_dlopen_ext(filename, flags, extinfo, caller)
+        }
+        _ => std::mem::transmute::<*mut c_void, DlopenExtFn>(original)(filename, flags, extinfo),
+    };
+    after_dlopen(handle, filename);
+    handle
+}
+
+/// Hook the library a dlopen call loaded, a null filename is the app itself and was there from the start
+unsafe fn after_dlopen(handle: *mut c_void, filename: *const c_char) {
+    if !handle.is_null() && !filename.is_null() {
+        crate::hook_loaded(CStr::from_ptr(filename));
+    }
+}
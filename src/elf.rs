@@ -0,0 +1,292 @@
+// Just enough ELF reading to check signature hits against the library file
+use scroll::{Pread, LE};
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+const EM_ARM: u16 = 40;
+
+/// A loadable segment, used to turn file offsets into addresses
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Section {
+    name: u32,
+    kind: u32,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    /// Without the Thumb bit on arm
+    pub value: u64,
+    pub size: u64,
+}
+
+/// The parts of a library we need, addresses are the ones in the file before relocation
+#[derive(Debug, Clone, Default)]
+pub struct ElfInfo {
+    /// The e_machine field
+    pub machine: u16,
+    segments: Vec<Segment>,
+    pub text: Option<Range<u64>>,
+    /// Functions from .symtab and .dynsym, empty if the library is fully stripped
+    pub symbols: Vec<Symbol>,
+}
+
+impl ElfInfo {
+    /// Read the headers and symbol tables, without loading the whole library
+    pub fn read(file: &mut (impl Read + Seek)) -> io::Result<Self> {
+        let ident = read_at(file, 0, 16)?;
+        if ident.get(..4) != Some(b"\x7FELF".as_slice()) {
+            return Err(invalid("Not an ELF file"));
+        }
+        let is_64 = match ident.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err(invalid("Unknown ELF class")),
+        };
+        // Every Android ABI is little endian
+        if ident.get(5) != Some(&1) {
+            return Err(invalid("Not a little endian ELF"));
+        }
+        let header = read_at(file, 0, if is_64 { 64 } else { 52 })?;
+        let machine: u16 = header.pread_with(18, LE).map_err(invalid)?;
+        let reader = Reader {
+            is_64,
+            thumb: machine == EM_ARM,
+        };
+        let offset = &mut 24;
+        let _entry = reader.word(&header, offset)?;
+        let phoff = reader.word(&header, offset)?;
+        let shoff = reader.word(&header, offset)?;
+        *offset += 6;
+        let phentsize: u16 = header.gread_with(offset, LE).map_err(invalid)?;
+        let phnum: u16 = header.gread_with(offset, LE).map_err(invalid)?;
+        let shentsize: u16 = header.gread_with(offset, LE).map_err(invalid)?;
+        let shnum: u16 = header.gread_with(offset, LE).map_err(invalid)?;
+        let shstrndx: u16 = header.gread_with(offset, LE).map_err(invalid)?;
+
+        let phdrs = read_at(file, phoff, usize::from(phentsize) * usize::from(phnum))?;
+        let segments = phdrs
+            .chunks(phentsize.into())
+            .map(|phdr| reader.segment(phdr))
+            .filter_map(Result::transpose)
+            .collect::<io::Result<_>>()?;
+
+        let shdrs = read_at(file, shoff, usize::from(shentsize) * usize::from(shnum))?;
+        let sections = shdrs
+            .chunks(shentsize.into())
+            .map(|shdr| reader.section(shdr))
+            .collect::<io::Result<Vec<_>>>()?;
+        let names = match sections.get(usize::from(shstrndx)) {
+            Some(strtab) => read_at(file, strtab.offset, to_usize(strtab.size)?)?,
+            None => Vec::new(),
+        };
+        let text = sections
+            .iter()
+            .find(|section| c_str(&names, section.name) == Some(b".text".as_slice()))
+            .map(|section| section.addr..section.addr.saturating_add(section.size));
+
+        let mut symbols = Vec::new();
+        for table in sections
+            .iter()
+            .filter(|section| matches!(section.kind, SHT_SYMTAB | SHT_DYNSYM))
+        {
+            let Some(strings) = sections.get(table.link as usize) else {
+                continue;
+            };
+            let entries = read_at(file, table.offset, to_usize(table.size)?)?;
+            let strings = read_at(file, strings.offset, to_usize(strings.size)?)?;
+            for entry in entries.chunks_exact(if is_64 { 24 } else { 16 }) {
+                if let Some(symbol) = reader.symbol(entry, &strings)? {
+                    symbols.push(symbol);
+                }
+            }
+        }
+        Ok(Self {
+            machine,
+            segments,
+            text,
+            symbols,
+        })
+    }
+    /// The address a file offset gets loaded at
+    pub fn offset_to_vaddr(&self, offset: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|segment| offset >= segment.offset && offset - segment.offset < segment.filesz)
+            .map(|segment| segment.vaddr + (offset - segment.offset))
+    }
+    /// Where in the file the bytes loaded at `vaddr` are
+    #[allow(dead_code)] // Only the host tools go this way
+    pub fn vaddr_to_offset(&self, vaddr: u64) -> Option<u64> {
+        self.segments
+            .iter()
+            .find(|segment| vaddr >= segment.vaddr && vaddr - segment.vaddr < segment.filesz)
+            .map(|segment| segment.offset + (vaddr - segment.vaddr))
+    }
+    /// The function covering `vaddr`
+    pub fn symbol_at(&self, vaddr: u64) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| {
+            vaddr == symbol.value || (vaddr > symbol.value && vaddr - symbol.value < symbol.size)
+        })
+    }
+    /// Check that `vaddr` could be the start of the function named `expected`.
+    /// Names are compared by prefix so every constructor variant is accepted
+    pub fn check_function(&self, vaddr: u64, alignment: u64, expected: &str) -> Result<(), String> {
+        if self
+            .text
+            .as_ref()
+            .is_some_and(|text| !text.contains(&vaddr))
+        {
+            return Err(format!("{vaddr:x} is outside of .text"));
+        }
+        if !vaddr.is_multiple_of(alignment) {
+            return Err(format!("{vaddr:x} is not aligned to {alignment} bytes"));
+        }
+        if let Some(symbol) = self.symbol_at(vaddr) {
+            if symbol.value != vaddr {
+                return Err(format!("{vaddr:x} is inside of {}", symbol.name));
+            }
+            if !symbol.name.starts_with(expected) {
+                return Err(format!("{vaddr:x} is {}", symbol.name));
+            }
+            return Ok(());
+        }
+        // Nothing covers the address, which is fine unless the function we want is somewhere else
+        match self
+            .symbols
+            .iter()
+            .find(|symbol| symbol.name.starts_with(expected))
+        {
+            Some(symbol) => Err(format!("{} is at {:x}", symbol.name, symbol.value)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads the fields that are 32 or 64 bits wide depending on the class
+struct Reader {
+    is_64: bool,
+    /// Thumb function symbols have the lowest bit set
+    thumb: bool,
+}
+
+impl Reader {
+    fn word(&self, bytes: &[u8], offset: &mut usize) -> io::Result<u64> {
+        if self.is_64 {
+            bytes.gread_with(offset, LE).map_err(invalid)
+        } else {
+            bytes
+                .gread_with::<u32>(offset, LE)
+                .map(u64::from)
+                .map_err(invalid)
+        }
+    }
+    fn u32(&self, bytes: &[u8], offset: &mut usize) -> io::Result<u32> {
+        bytes.gread_with(offset, LE).map_err(invalid)
+    }
+    fn segment(&self, phdr: &[u8]) -> io::Result<Option<Segment>> {
+        let offset = &mut 0;
+        let kind = self.u32(phdr, offset)?;
+        if self.is_64 {
+            // Flags come right after the type on 64 bit
+            *offset += 4;
+        }
+        let file_offset = self.word(phdr, offset)?;
+        let vaddr = self.word(phdr, offset)?;
+        let _paddr = self.word(phdr, offset)?;
+        let filesz = self.word(phdr, offset)?;
+        Ok((kind == PT_LOAD).then_some(Segment {
+            offset: file_offset,
+            vaddr,
+            filesz,
+        }))
+    }
+    fn section(&self, shdr: &[u8]) -> io::Result<Section> {
+        let offset = &mut 0;
+        let name = self.u32(shdr, offset)?;
+        let kind = self.u32(shdr, offset)?;
+        let _flags = self.word(shdr, offset)?;
+        let addr = self.word(shdr, offset)?;
+        let file_offset = self.word(shdr, offset)?;
+        let size = self.word(shdr, offset)?;
+        let link = self.u32(shdr, offset)?;
+        Ok(Section {
+            name,
+            kind,
+            addr,
+            offset: file_offset,
+            size,
+            link,
+        })
+    }
+    /// Only defined functions are returned
+    fn symbol(&self, entry: &[u8], strings: &[u8]) -> io::Result<Option<Symbol>> {
+        let offset = &mut 0;
+        let name = self.u32(entry, offset)?;
+        let (info, shndx, value, size);
+        if self.is_64 {
+            info = entry.gread_with::<u8>(offset, LE).map_err(invalid)?;
+            *offset += 1;
+            shndx = entry.gread_with::<u16>(offset, LE).map_err(invalid)?;
+            value = self.word(entry, offset)?;
+            size = self.word(entry, offset)?;
+        } else {
+            value = self.word(entry, offset)?;
+            size = self.word(entry, offset)?;
+            info = entry.gread_with::<u8>(offset, LE).map_err(invalid)?;
+            *offset += 1;
+            shndx = entry.gread_with::<u16>(offset, LE).map_err(invalid)?;
+        }
+        if info & 0xF != STT_FUNC || shndx == SHN_UNDEF {
+            return Ok(None);
+        }
+        let Some(name) = c_str(strings, name) else {
+            return Ok(None);
+        };
+        Ok(Some(Symbol {
+            name: String::from_utf8_lossy(name).into_owned(),
+            value: if self.thumb { value & !1 } else { value },
+            size,
+        }))
+    }
+}
+
+fn read_at(file: &mut (impl Read + Seek), offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// The nul terminated string at `offset` of a string table
+fn c_str(table: &[u8], offset: u32) -> Option<&[u8]> {
+    let rest = table.get(offset as usize..)?;
+    let end = rest.iter().position(|byte| *byte == 0)?;
+    rest.get(..end)
+}
+
+fn to_usize(value: u64) -> io::Result<usize> {
+    usize::try_from(value).map_err(invalid)
+}
+
+fn invalid(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -1,80 +1,228 @@
+use crate::materialbin::{MinecraftVersion, ALL_VERSIONS};
 use jni::{
-    objects::JObject,
-    sys::jboolean,
+    objects::{JObject, JObjectArray, JString},
+    sys::{jboolean, JNI_TRUE},
     JNIEnv,
 };
-// use materialbin::{MinecraftVersion, ALL_VERSIONS};
+use std::{
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
 
-// use crate::LockResultExt;
-// pub struct Options {
-//     pub handle_lightmaps: bool,
-//     pub handle_texturelods: bool,
-//     pub autofixer_versions: Vec<MinecraftVersion>,
-// }
-// impl Default for Options {
-//     fn default() -> Self {
-//         Self {
-//             handle_lightmaps: true,
-//             handle_texturelods: true,
-//             autofixer_versions: ALL_VERSIONS.to_vec(),
-//         }
-//     }
-// }
-// pub static OPTS: LazyLock<Mutex<Options>> = LazyLock::new(|| Mutex::new(Options::default()));
+#[cfg(target_os = "android")]
+use crate::aasset;
+use crate::{
+    plthook::HookTarget,
+    redirect::{RedirectTable, REDIRECTS},
+    source::{self, OverridePriority},
+    LockResultExt,
+};
+pub struct Options {
+    pub handle_lightmaps: bool,
+    pub handle_texturelods: bool,
+    pub autofixer_versions: Vec<MinecraftVersion>,
+    /// Folder with loose files that replace apk files
+    pub override_dir: Option<PathBuf>,
+    pub override_priority: OverridePriority,
+    /// A .zip or .mcpack served like an active resource pack
+    pub archive: Option<PathBuf>,
+    /// Libraries that get the AssetManager hooks
+    pub hook_targets: Vec<HookTarget>,
+    /// AAsset functions hooked inside libandroid instead of through the PLT
+    #[cfg_attr(test, allow(dead_code))]
+    pub inline_symbols: Vec<String>,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            handle_lightmaps: true,
+            handle_texturelods: true,
+            autofixer_versions: ALL_VERSIONS.to_vec(),
+            override_dir: None,
+            override_priority: OverridePriority::default(),
+            archive: None,
+            hook_targets: HookTarget::defaults(),
+            inline_symbols: Vec::new(),
+        }
+    }
+}
+pub static OPTS: LazyLock<Mutex<Options>> = LazyLock::new(|| Mutex::new(Options::default()));
 #[no_mangle]
 extern "C" fn Java_io_bambosan_mbloader_launcherUtils_LibBindings_setAutofixVersions(
-    _env: JNIEnv,
+    mut env: JNIEnv,
     _thiz: JObject,
-    _versions: jni::objects::JObjectArray,
+    versions: JObjectArray,
 ) {
-    // let sus = env
-    //     .get_array_length(&versions)
-    //     .expect("Error while getting array length");
-    // let mut rs_versions = Vec::new();
-    // for index in 0..sus {
-    //     let string = env
-    //         .get_object_array_element(&versions, index)
-    //         .expect("Error while reading jni array element");
-    //     let string: JString = string.into();
-    //     //        if !env.is_instance_of(string, "String")
-    //     let sus = env
-    //         .get_string(&string)
-    //         .expect("Error while getting jni string");
-    //     rs_versions.push(
-    //         version_from_string(sus.to_str().expect("Java string isnt utf8"))
-    //             .expect("Version string didnt match any mtbin format "),
-    //     );
-    // }
-    // let mut opts = OPTS.lock().ignore_poison();
-    // opts.autofixer_versions = rs_versions;
+    // A bad entry should not take the whole list down with it
+    let rs_versions: Vec<_> = string_array(&mut env, &versions)
+        .iter()
+        .filter_map(|string| {
+            let version = version_from_string(string);
+            if version.is_none() {
+                log::warn!("Version string {string} didnt match any mtbin format");
+            }
+            version
+        })
+        .collect();
+    log::info!("Autofixer versions set to: {:?}", rs_versions);
+    let mut opts = OPTS.lock().ignore_poison();
+    opts.autofixer_versions = rs_versions;
+}
+#[no_mangle]
+extern "C" fn Java_io_bambosan_mbloader_launcherUtils_LibBindings_setHookTargets(
+    mut env: JNIEnv,
+    _thiz: JObject,
+    targets: JObjectArray,
+) {
+    let rs_targets: Vec<_> = string_array(&mut env, &targets)
+        .iter()
+        .map(|target| HookTarget::new(target))
+        .collect();
+    log::info!("Hook targets set to: {:?}", rs_targets);
+    OPTS.lock().ignore_poison().hook_targets = rs_targets;
+    // Libraries already hooked stay hooked, new targets get picked up right away
+    #[cfg(target_os = "android")]
+    if aasset::hooks_installed() {
+        crate::hook_aaset();
+    }
+}
+/// Read a java String[], entries that can't be read are logged and left out
+fn string_array(env: &mut JNIEnv, array: &JObjectArray) -> Vec<String> {
+    let len = match env.get_array_length(array) {
+        Ok(len) => len,
+        Err(e) => {
+            log::error!("Error while getting array length: {e}");
+            return Vec::new();
+        }
+    };
+    let mut strings = Vec::new();
+    for index in 0..len {
+        let string: JString = match env.get_object_array_element(array, index) {
+            Ok(obj) => obj.into(),
+            Err(e) => {
+                log::error!("Error while reading jni array element: {e}");
+                continue;
+            }
+        };
+        let string = match env.get_string(&string) {
+            Ok(string) => String::from(string),
+            Err(e) => {
+                log::error!("Error while getting jni string: {e}");
+                continue;
+            }
+        };
+        strings.push(string);
+    }
+    strings
+}
+fn version_from_string(string: &str) -> Option<MinecraftVersion> {
+    let mcversion = match string {
+        "v1.18.30" => MinecraftVersion::V1_18_30,
+        "v1.19.60" => MinecraftVersion::V1_19_60,
+        "v1.20.80" => MinecraftVersion::V1_20_80,
+        "v1.21.20" => MinecraftVersion::V1_21_20,
+        "v1.21.110" => MinecraftVersion::V1_21_110,
+        "v26.0.24" => MinecraftVersion::V26_0_24,
+        _ => return None,
+    };
+    Some(mcversion)
 }
-// fn version_from_string(string: &str) -> Option<MinecraftVersion> {
-//     let mcversion = match string {
-//         "v1.18.30" => MinecraftVersion::V1_18_30,
-//         "v1.19.60" => MinecraftVersion::V1_19_60,
-//         "v1.20.80" => MinecraftVersion::V1_20_80,
-//         "v1.21.20" => MinecraftVersion::V1_21_20,
-//         "v1.21.110" => MinecraftVersion::V1_21_110,
-//         "v26.0.24" => MinecraftVersion::V26_0_24,
-//         _ => return None,
-//     };
-//     Some(mcversion)
-// }
 #[no_mangle]
 extern "C" fn Java_io_bambosan_mbloader_launcherUtils_LibBindings_setLightmapAutofixer(
-    mut _env: JNIEnv,
+    _env: JNIEnv,
     _thiz: JObject,
-    _on: jboolean,
+    on: jboolean,
 ) {
-    // let mut opts = OPTS.lock().ignore_poison();
-    // opts.handle_lightmaps = on == JNI_TRUE;
+    let mut opts = OPTS.lock().ignore_poison();
+    opts.handle_lightmaps = on == JNI_TRUE;
 }
 #[no_mangle]
 extern "C" fn Java_io_bambosan_mbloader_launcherUtils_LibBindings_setTextureLodAutofixer(
-    mut _env: JNIEnv,
+    _env: JNIEnv,
+    _thiz: JObject,
+    on: jboolean,
+) {
+    let mut opts = OPTS.lock().ignore_poison();
+    opts.handle_texturelods = on == JNI_TRUE;
+}
+#[no_mangle]
+extern "C" fn Java_io_bambosan_mbloader_launcherUtils_LibBindings_setRedirects(
+    mut env: JNIEnv,
+    _thiz: JObject,
+    table: JString,
+) {
+    let table = match env.get_string(&table) {
+        Ok(table) => String::from(table),
+        Err(e) => {
+            log::error!("Error while getting jni string: {e}");
+            return;
+        }
+    };
+    *REDIRECTS.lock().ignore_poison() = RedirectTable::parse(&table);
+    source::sources_changed();
+}
+#[no_mangle]
+extern "C" fn Java_io_bambosan_mbloader_launcherUtils_LibBindings_setOverrideDirectory(
+    mut env: JNIEnv,
+    _thiz: JObject,
+    dir: JString,
+    before_packs: jboolean,
+) {
+    // A null or empty path turns the override folder off
+    let dir = match dir.is_null() {
+        true => String::new(),
+        false => match env.get_string(&dir) {
+            Ok(dir) => String::from(dir),
+            Err(e) => {
+                log::error!("Error while getting jni string: {e}");
+                return;
+            }
+        },
+    };
+    let mut opts = OPTS.lock().ignore_poison();
+    opts.override_dir = (!dir.is_empty()).then(|| PathBuf::from(dir));
+    opts.override_priority = match before_packs == JNI_TRUE {
+        true => OverridePriority::BeforePacks,
+        false => OverridePriority::AfterPacks,
+    };
+    log::info!(
+        "Override directory set to {:?} ({:?})",
+        opts.override_dir,
+        opts.override_priority
+    );
+    source::sources_changed();
+}
+#[no_mangle]
+extern "C" fn Java_io_bambosan_mbloader_launcherUtils_LibBindings_setArchive(
+    mut env: JNIEnv,
+    _thiz: JObject,
+    path: JString,
+) {
+    // A null or empty path turns the archive off
+    let path = match path.is_null() {
+        true => String::new(),
+        false => match env.get_string(&path) {
+            Ok(path) => String::from(path),
+            Err(e) => {
+                log::error!("Error while getting jni string: {e}");
+                return;
+            }
+        },
+    };
+    let mut opts = OPTS.lock().ignore_poison();
+    opts.archive = (!path.is_empty()).then(|| PathBuf::from(path));
+    log::info!("Archive set to {:?}", opts.archive);
+    source::sources_changed();
+}
+#[cfg(target_os = "android")]
+#[no_mangle]
+extern "C" fn Java_io_bambosan_mbloader_launcherUtils_LibBindings_setAssetHooks(
+    _env: JNIEnv,
     _thiz: JObject,
-    _on: jboolean,
+    on: jboolean,
 ) {
-    // let mut opts = OPTS.lock().ignore_poison();
-    // opts.handle_texturelods = on == JNI_TRUE;
+    match on == JNI_TRUE {
+        true => crate::hook_aaset(),
+        false => crate::unhook_aaset(),
+    }
 }
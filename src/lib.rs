@@ -1,54 +1,71 @@
+// Only the hooks need Android, tests also build what they use on the host.
+// The host tools in src/bin get the pub modules
+#[cfg(any(target_os = "android", test))]
 #[deny(clippy::indexing_slicing)]
 mod cpp_string;
+pub mod decode;
+#[cfg(any(target_os = "android", test))]
+mod detect;
+#[cfg(any(target_os = "android", test))]
 mod loader;
-use std::{
-    fs,
-    sync::{LockResult, Mutex},
-};
+#[cfg(any(target_os = "android", test))]
+mod maps;
+use std::sync::LockResult;
+#[cfg(any(target_os = "android", test))]
+use std::{fs, path::PathBuf, sync::Mutex};
+#[cfg(target_os = "android")]
 mod aasset;
+#[cfg(any(target_os = "android", test))]
+mod autofix;
+#[cfg(any(target_os = "android", test))]
+mod cache;
+#[cfg(target_os = "android")]
+mod dlopen;
+pub mod elf;
+#[cfg(any(target_os = "android", test))]
 mod jniopts;
+#[cfg(any(target_os = "android", test))]
+mod materialbin;
+pub mod pattern;
+#[cfg(any(target_os = "android", test))]
 mod plthook;
-use crate::{loader::ResourcePackManager, plthook::replace_plt_functions};
+#[cfg(any(target_os = "android", test))]
+mod redirect;
+pub mod signatures;
+#[cfg(any(target_os = "android", test))]
+mod source;
+pub mod version;
+#[cfg(any(target_os = "android", test))]
+mod zip;
+#[cfg(target_os = "android")]
+use crate::{
+    decode::Arch,
+    elf::ElfInfo,
+    maps::{Maps, MapsEntry, Perms},
+    signatures::{SignatureDb, RPMC_SYMBOL},
+    version::GameVersion,
+};
+#[cfg(target_os = "android")]
+use crate::{
+    jniopts::OPTS,
+    plthook::{replace_plt_functions, HookTarget},
+    redirect::{RedirectTable, REDIRECTS},
+};
+#[cfg(target_os = "android")]
 use bhook::hook_fn;
+#[cfg(any(target_os = "android", test))]
 use bstr::ByteSlice;
-//use bstr::ByteSlice;
-use atoi::FromRadix16;
+#[cfg(target_os = "android")]
 use plt_rs::DynamicLibrary;
-use tinypatscan::Pattern;
-
-#[cfg(target_arch = "aarch64")]
-const RPMC_PATTERNS: [Pattern; 3] = [
-    //1.21.120.4
-    Pattern::from_str("FF ?? 02 D1 FD 7B ?? A9 ?? ?? ?? ?? FA 67 ?? A9 F8 5F ?? A9 F6 57 ?? A9 F4 4F ?? A9 FD ?? 01 91 ?? D0 3B D5 ?? 03 03 2A ?? 03 02 AA ?? 17 40 F9 F3 03 00 AA A8 83 1F F8"),
-    // V1.21.60.21
-    Pattern::from_str("FF 83 02 D1 FD 7B 06 A9 FD 83 01 91 F8 5F 07 A9 F6 57 08 A9 F4 4F 09 A9 58 D0 3B D5 F6 03 03 2A 08 17 40 F9 F5 03 02 AA F3 03 00 AA A8 83 1F F8 28 10 40 F9 28 01 00 B4"),
-    // V1.19.50-1.21.50
-    Pattern::from_str("FF 03 03 D1 FD 7B 07 A9 FD C3 01 91 F9 43 00 F9 F8 5F 09 A9 F6 57 0A A9 F4 4F 0B A9 59 D0 3B D5 F6 03 03 2A 28 17 40 F9 F5 03 02 AA F3 03 00 AA A8 83 1F F8 28 10 40 F9"),
-];
-#[cfg(target_arch = "arm")]
-const RPMC_PATTERNS: [Pattern; 2] = [
-    //1.21.120.4
-    Pattern::from_str(
-        "F0 B5 03 AF 2D E9 00 0F 8B B0 82 46 DF F8 ?? ?? 9B 46 91 46 78 44 00 68 00 68 0A 90",
-    ),
-    // V1.21.110-1.19.50
-    Pattern::from_str(
-        "F0 B5 03 AF 2D E9 00 ?? ?? B0 ?? 46 ?? 48 98 46 92 46 78 44 00 68 00 68 ?? 90 08 69",
-    ),
-];
-
-#[cfg(target_arch = "x86_64")]
-const RPMC_PATTERNS: [Pattern; 2] = [
-    Pattern::from_str("55 41 57 41 56 41 55 41 54 53 48 83 EC ? 41 89 CF 49 89 D6 48 89 FB 64 48 8B 04 25 28 00 00 00 48 89 44 24 ? 48 8B 7E"),
-    Pattern::from_str("55 41 57 41 56 53 48 83 EC ? 41 89 CF 49 89 D6 48 89 FB 64 48 8B 04 25 28 00 00 00 48 89 44 24 ? 48 8B 7E"),
-];
 
 // Just setup the logger so we see those logcats
+#[cfg(target_os = "android")]
 pub fn setup_logging() {
     android_logger::init_once(
         android_logger::Config::default().with_max_level(log::LevelFilter::Trace),
     );
 }
+#[cfg(target_os = "android")]
 #[ctor::ctor]
 fn safe_setup() {
     setup_logging();
@@ -58,99 +75,193 @@ fn safe_setup() {
     // Let it crash and burn if anything happens
     main();
 }
+#[cfg(target_os = "android")]
 fn main() {
     log::info!("Starting, mbl2 version v0.1.12");
+    *REDIRECTS.lock().ignore_poison() = RedirectTable::load();
+    {
+        let mut opts = OPTS.lock().ignore_poison();
+        opts.hook_targets = HookTarget::load_list();
+        opts.inline_symbols = read_config_list("inline_hooks.txt").unwrap_or_default();
+    }
     let mcmaps = find_minecraft_library_manually()
         .expect("Cannot find libminecraftpe.so in memory maps - device not supported");
-    let addr = find_signatures(&RPMC_PATTERNS, &mcmaps).expect("No signature was found");
-    log::info!("Hooking ResourcePackManager constructor");
-    unsafe {
-        rpm_ctor::hook_address(addr as *mut u8);
-    };
+    let data: Vec<&[u8]> = mcmaps
+        .entries
+        .iter()
+        .filter(|range| !range.perms.execute)
+        .map(|range| unsafe {
+            core::slice::from_raw_parts(range.start() as *const u8, range.size())
+        })
+        .collect();
+    let version = detect::init(&data);
+    let signatures = load_signatures();
+    // The override folder and archives still work without the pack manager
+    match find_signatures(&signatures, version, &mcmaps) {
+        Some(addr) => {
+            log::info!("Hooking ResourcePackManager constructor");
+            unsafe {
+                rpm_ctor::hook_address(addr as *mut u8);
+            };
+        }
+        None => log::error!("No signature was found, resource packs can't be loaded"),
+    }
     log::info!("Hooking AssetManager functions");
     hook_aaset();
 }
-// A very minimal map range
-#[derive(Debug)]
-struct SimpleMapRange {
-    start: usize,
-    size: usize,
-}
-
-impl SimpleMapRange {
-    /// Get the address where this range starts
-    const fn start(&self) -> usize {
-        self.start
-    }
-
-    /// Get the address where this range ends
-    const fn size(&self) -> usize {
-        self.size
+/// Get the files dir of the app we are running in
+#[cfg(any(target_os = "android", test))]
+pub fn files_dir() -> Option<PathBuf> {
+    let cmdline = fs::read("/proc/self/cmdline").ok()?;
+    // Process name is the package name, services add a :suffix
+    let name = cmdline.split(|c| *c == 0).next()?;
+    let package = name.split(|c| *c == b':').next()?.to_str().ok()?;
+    if package.is_empty() {
+        return None;
     }
+    Some(PathBuf::from(format!("/data/data/{package}/files")))
 }
-
-fn find_minecraft_library_manually() -> Result<Vec<SimpleMapRange>, Box<dyn std::error::Error>> {
-    let contents = fs::read("/proc/self/maps")?;
-    let mut ranges = Vec::new();
-    for line in contents.lines() {
-        if line.trim_ascii().is_empty() {
-            continue;
-        }
-        // Not too pretty but this method prevents crashes
-        let Some((addr_start, addr_end)) = parse_range(line) else {
-            continue;
-        };
-        let start = usize::from_radix_16(addr_start).0;
-        let end = usize::from_radix_16(addr_end).0;
-        log::info!("Found libminecraftpe.so region at: {:x}-{:x}", start, end);
-        ranges.push(SimpleMapRange {
-            start,
-            size: end - start,
-        });
+/// Read a list from a file in the launcher files dir, one entry per line.
+/// Empty lines and lines starting with # are ignored
+#[cfg(any(target_os = "android", test))]
+pub fn read_config_list(name: &str) -> Option<Vec<String>> {
+    let path = files_dir()?.join("mbl2").join(name);
+    let text = fs::read_to_string(&path).ok()?;
+    log::info!("Loading {}", path.display());
+    let list = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect();
+    Some(list)
+}
+/// Every readable mapping of the game library
+#[cfg(target_os = "android")]
+fn find_minecraft_library_manually() -> Result<Maps, Box<dyn std::error::Error>> {
+    let maps = Maps::read_self()?;
+    // Code gets scanned for signatures, the rest only for the version string
+    let entries: Vec<MapsEntry> = maps
+        .by_name("libminecraftpe.so")
+        .filter(|entry| !entry.deleted && (entry.perms.read || entry.perms.execute))
+        .cloned()
+        .collect();
+    for range in &entries {
+        log::info!(
+            "Found libminecraftpe.so region at: {:x}-{:x}",
+            range.range.start,
+            range.range.end
+        );
     }
 
-    if ranges.is_empty() {
+    if entries.is_empty() {
         Err("libminecraftpe.so not found in memory maps".into())
     } else {
-        Ok(ranges)
+        Ok(Maps { entries })
     }
 }
-/// Separated into function due to option spam
-fn parse_range(buf: &[u8]) -> Option<(&[u8], &[u8])> {
-    let mut line = buf.split(|v| v.is_ascii_whitespace());
-    let addr_range = line.next()?;
-    let perms = line.next()?;
-    let pathname = line.next_back()?;
-    if perms.contains(&b'x') && pathname.ends_with(b"libminecraftpe.so") {
-        return addr_range.split_once_str(b"-");
+
+#[cfg(all(target_os = "android", target_arch = "aarch64"))]
+const CURRENT_ARCH: Arch = Arch::Aarch64;
+#[cfg(all(target_os = "android", target_arch = "arm"))]
+const CURRENT_ARCH: Arch = Arch::Arm;
+#[cfg(all(target_os = "android", target_arch = "x86_64"))]
+const CURRENT_ARCH: Arch = Arch::X86_64;
+#[cfg(all(target_os = "android", target_arch = "x86"))]
+const CURRENT_ARCH: Arch = Arch::X86;
+/// Use the launcher's signatures.txt if it has entries for us, the built in ones otherwise
+#[cfg(target_os = "android")]
+fn load_signatures() -> SignatureDb {
+    let Some(path) = files_dir().map(|dir| dir.join("mbl2/signatures.txt")) else {
+        return SignatureDb::builtin();
+    };
+    let Ok(text) = fs::read_to_string(&path) else {
+        return SignatureDb::builtin();
+    };
+    let (db, errors) = SignatureDb::parse(&text);
+    for error in &errors {
+        log::error!("Invalid signature in {}, {error}", path.display());
+    }
+    if !db.has_arch(CURRENT_ARCH) {
+        log::warn!(
+            "{} has no usable signatures, using the built in ones",
+            path.display()
+        );
+        return SignatureDb::builtin();
     }
-    None
+    log::info!(
+        "Loaded {} signatures from {}",
+        db.entries.len(),
+        path.display()
+    );
+    db
 }
 
-fn find_signatures(signatures: &[Pattern], ranges: &[SimpleMapRange]) -> Option<*const u8> {
-    for sig in signatures {
-        for range in ranges {
+#[cfg(target_os = "android")]
+fn find_signatures(
+    signatures: &SignatureDb,
+    version: Option<GameVersion>,
+    library: &Maps,
+) -> Option<*const u8> {
+    let regions: Vec<(usize, &[u8])> = library
+        .by_perms(Perms::EXECUTE)
+        .map(|range| {
             let libbytes =
                 unsafe { core::slice::from_raw_parts(range.start() as *const u8, range.size()) };
-            let addr = sig.search(libbytes, tinypatscan::Algorithm::Simd);
-            if let Some(val) = addr {
-                let addr = unsafe { libbytes.as_ptr().byte_add(val) };
-                #[cfg(target_arch = "arm")]
-                let addr = unsafe { addr.offset(1) };
-                log::info!(
-                    "Found signature in region {:x}-{:x} at offset {:x}",
-                    range.start(),
-                    range.start() + range.size(),
-                    val
-                );
-                return Some(addr);
-            }
-        }
-        log::error!("Cannot find signature in any region");
+            (range.start(), libbytes)
+        })
+        .collect();
+    let elf = read_library_elf(library);
+    if elf.is_none() {
+        log::warn!("Cannot read libminecraftpe.so, signature targets are not validated");
     }
-    None
+    let addr =
+        signatures::find_function(
+            signatures,
+            CURRENT_ARCH,
+            version,
+            &regions,
+            |target| match &elf {
+                Some(elf) => check_target(elf, library, target),
+                None => Ok(()),
+            },
+        )?;
+    let addr = addr as *const u8;
+    #[cfg(target_arch = "arm")]
+    let addr = unsafe { addr.offset(1) };
+    Some(addr)
 }
 
+#[cfg(target_os = "android")]
+fn read_library_elf(library: &Maps) -> Option<ElfInfo> {
+    let path = &library.entries.first()?.pathname;
+    let mut file = fs::File::open(path).ok()?;
+    let elf = ElfInfo::read(&mut file)
+        .inspect_err(|e| log::warn!("Cannot parse {}: {e}", path.display()))
+        .ok()?;
+    if Arch::from_elf_machine(elf.machine) != Some(CURRENT_ARCH) {
+        log::warn!("{} is not a {CURRENT_ARCH} library", path.display());
+        return None;
+    }
+    Some(elf)
+}
+/// Make sure a signature target is the start of a function in .text, and the right one if there are symbols
+#[cfg(target_os = "android")]
+fn check_target(elf: &ElfInfo, library: &Maps, target: usize) -> Result<(), String> {
+    let range = library
+        .find_address(target)
+        .ok_or("not inside of libminecraftpe.so")?;
+    if !range.perms.execute {
+        return Err("not inside of code".to_owned());
+    }
+    let file_offset = target - range.start() + range.offset;
+    let vaddr = elf
+        .offset_to_vaddr(file_offset as u64)
+        .ok_or("not inside of a loaded segment")?;
+    elf.check_function(vaddr, CURRENT_ARCH.function_alignment() as u64, RPMC_SYMBOL)
+}
+
+#[cfg(target_os = "android")]
 macro_rules! cast_array {
     ($($func_name:literal -> $hook:expr),
         *,
@@ -160,10 +271,84 @@ macro_rules! cast_array {
         ]
     }
 }
-/// Set up the asset manager hooks so we control APK file access
+// Without these we would hand out assets the game can never read or free
+#[cfg(target_os = "android")]
+const CORE_AASSET_FUNCTIONS: [&str; 3] = ["AAssetManager_open", "AAsset_read", "AAsset_close"];
+// Libraries that got their hooks, the ones that failed are tried again on the next hook_aaset
+#[cfg(target_os = "android")]
+static SEEN_LIBRARIES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Set up the asset manager hooks in every target library so we control APK file access
+#[cfg(target_os = "android")]
 pub fn hook_aaset() {
-    let lib_entry = find_lib("libminecraftpe").expect("Cannot find minecraftpe");
-    let dyn_lib = DynamicLibrary::initialize(lib_entry).expect("Failed to find mc info");
+    let (targets, inline_symbols) = {
+        let opts = OPTS.lock().ignore_poison();
+        (opts.hook_targets.clone(), opts.inline_symbols.clone())
+    };
+    if !inline_symbols.is_empty() {
+        aasset::install_inline_hooks(&inline_symbols);
+    }
+    let mut tried = 0;
+    for lib_entry in plt_rs::collect_modules() {
+        if hook_library(lib_entry, &targets) == Some(true) {
+            tried += 1;
+        }
+    }
+    if tried != 0 && !aasset::hooks_installed() {
+        log::error!("AssetManager functions are not hooked in any library");
+    }
+}
+/// Hook a library dlopen just loaded, if it is a target or a loader.
+/// Libraries it pulled in as dependencies are left to the next hook_aaset
+#[cfg(target_os = "android")]
+pub fn hook_loaded(filename: &std::ffi::CStr) {
+    // Without a slash the linker searched for it, either way the file name is the same
+    let filename = filename.to_string_lossy();
+    let file_name = |path: &str| path.rsplit('/').next().map(str::to_owned);
+    let wanted = file_name(&filename);
+    let targets = OPTS.lock().ignore_poison().hook_targets.clone();
+    for lib_entry in plt_rs::collect_modules() {
+        if file_name(lib_entry.name()) == wanted {
+            hook_library(lib_entry, &targets);
+        }
+    }
+}
+/// Hook a loaded library if it is a target or loads libraries for the app.
+/// Returns whether it is a target, None if it is neither or already has its hooks
+#[cfg(target_os = "android")]
+fn hook_library(lib_entry: plt_rs::LoadedLibrary, targets: &[HookTarget]) -> Option<bool> {
+    let name = lib_entry.name().to_owned();
+    let is_target = targets.iter().any(|target| target.matches(&name));
+    let is_loader = dlopen::LOADER_LIBRARIES
+        .iter()
+        .any(|loader| name.ends_with(loader));
+    // The lock is not held while patching, dlopen hooks can run from any thread at the same time
+    if !(is_target || is_loader) || SEEN_LIBRARIES.lock().ignore_poison().contains(&name) {
+        return None;
+    }
+    let dyn_lib = match DynamicLibrary::initialize(lib_entry) {
+        Ok(dyn_lib) => dyn_lib,
+        Err(e) => {
+            log::error!("Failed to read dynamic info of {name}: {e}");
+            return Some(is_target);
+        }
+    };
+    let watching = dlopen::hook_dlopen_in(&dyn_lib, &name);
+    let hooked = match is_target {
+        true => hook_aaset_in(&dyn_lib, &name),
+        false => watching,
+    };
+    if hooked {
+        let mut seen = SEEN_LIBRARIES.lock().ignore_poison();
+        if !seen.contains(&name) {
+            seen.push(name);
+        }
+    }
+    Some(is_target)
+}
+/// Hook a single library, returns whether the core functions are hooked in it
+#[cfg(target_os = "android")]
+fn hook_aaset_in(dyn_lib: &DynamicLibrary, name: &str) -> bool {
+    log::info!("Hooking AssetManager functions in {name}");
     // Functions of aasset
     let asset_fn_list = cast_array! {
         "AAssetManager_open" -> aasset::open,
@@ -180,21 +365,41 @@ pub fn hook_aaset() {
         "AAsset_getBuffer" -> aasset::get_buffer,
         "AAsset_isAllocated" -> aasset::is_alloc,
     };
+    // A symbol hooked inline already goes through us from every library
+    let plt_fn_list = asset_fn_list
+        .into_iter()
+        .filter(|(symbol, _)| !aasset::is_inline_hooked(symbol));
+    let required: Vec<&str> = CORE_AASSET_FUNCTIONS
+        .into_iter()
+        .filter(|symbol| !aasset::is_inline_hooked(symbol))
+        .collect();
     //The actual work
-    replace_plt_functions(&dyn_lib, asset_fn_list);
+    let report = replace_plt_functions(dyn_lib, plt_fn_list, &required);
+    report.log(name);
+    let core_hooked = required.iter().all(|name| report.is_hooked(name));
+    aasset::register_hooks(name, report.into_hooks());
+    if !core_hooked {
+        log::error!("Core AAsset functions could not be hooked in {name}, undoing its other hooks");
+        aasset::uninstall_hooks(|library| library == name);
+    }
+    core_hooked
 }
-/// Find some library's PLT
-fn find_lib<'a>(target_name: &str) -> Option<plt_rs::LoadedLibrary<'a>> {
-    let loaded_modules = plt_rs::collect_modules();
-    loaded_modules
-        .into_iter()
-        .find(|lib| lib.name().contains(target_name))
+/// Undo the asset manager hooks, packs stop being able to replace apk files
+#[cfg(target_os = "android")]
+pub fn unhook_aaset() {
+    log::info!("Unhooking AssetManager functions");
+    dlopen::unhook_dlopen();
+    aasset::uninstall_hooks(|_| true);
+    aasset::disable_inline_hooks();
+    SEEN_LIBRARIES.lock().ignore_poison().clear();
 }
+#[cfg(any(target_os = "android", test))]
 // A resource pack manager object
-pub static PACKM_OBJ: Mutex<Option<ResourcePackManager>> = Mutex::new(None);
+pub static PACKM_OBJ: Mutex<Option<loader::ResourcePackManager>> = Mutex::new(None);
 // The resource pack manager load function
 // pub static RPM_LOAD: OnceLock<RpmLoadFn> = OnceLock::new();
 
+#[cfg(target_os = "android")]
 hook_fn! {
     fn rpm_ctor(this: *mut libc::c_void,unk1: usize,unk2: usize,needs_init: bool) -> *mut libc::c_void = {
 
@@ -1,5 +1,11 @@
+use crate::materialbin::{MinecraftVersion, ALL_VERSIONS};
 use crate::{
+    autofix,
+    cache::BufferCache,
     cpp_string::{ResourceLocation, StackString},
+    detect,
+    jniopts::OPTS,
+    source::{self, AssetSource},
     LockResultExt,
 };
 use cxx::CxxString;
@@ -11,6 +17,7 @@ use std::{
     os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
     pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 pub enum BufferCursor {
@@ -34,6 +41,7 @@ impl Seek for BufferCursor {
     }
 }
 impl BufferCursor {
+    #[cfg_attr(test, allow(dead_code))]
     pub fn position(&self) -> u64 {
         match self {
             Self::Vec(v) => v.position(),
@@ -47,70 +55,121 @@ impl BufferCursor {
         }
     }
 }
-macro_rules! folder_list {
-    ($( apk: $apk_folder:literal -> pack: $pack_folder:expr),
-        *,
-    ) => {
-        [
-            $(($apk_folder, $pack_folder)),*,
-        ]
-    }
-}
-
 pub struct FileLoader {
-    pub last_buffer: Option<Buffer>,
+    cache: BufferCache,
+    // Material format the game expects, found from the first vanilla material we see
+    game_version: Option<MinecraftVersion>,
+    // Checked in order, the first source that has the file wins
+    sources: Vec<Box<dyn AssetSource>>,
+    sources_generation: usize,
 }
 impl FileLoader {
+    #[cfg_attr(test, allow(dead_code))]
     pub fn new() -> Self {
-        Self { last_buffer: None }
+        let sources_generation = source::sources_generation();
+        Self {
+            cache: BufferCache::new(),
+            game_version: None,
+            sources: source::build_chain(&OPTS.lock().ignore_poison()),
+            sources_generation,
+        }
     }
-    pub fn get_file(&mut self, path: &Path) -> Option<Buffer> {
+    #[cfg(test)]
+    fn with_sources(sources: Vec<Box<dyn AssetSource>>) -> Self {
+        Self {
+            cache: BufferCache::new(),
+            game_version: None,
+            sources,
+            sources_generation: source::sources_generation(),
+        }
+    }
+    /// Get a replacement for an apk file, `vanilla` gives the original file contents if needed
+    pub fn get_file<'a>(
+        &mut self,
+        path: &Path,
+        vanilla: impl FnOnce() -> Option<&'a [u8]>,
+    ) -> Option<Buffer> {
         let stripped = path.strip_prefix("assets/").unwrap_or(path);
-        // if let Some(mut cache) = self.last_buffer.take_if(|c| c.name == path) {
-        //     log::info!("Cache hit!: {:#?}", path);
-        //     cache
-        //         .rewind()
-        //         .expect("Unable to rewind in a memory buffer?, impossible");
-        //     return Some(cache);
-        // }
-        let replacement_list = folder_list! {
-            apk: "gui/dist/hbui/" -> pack: "hbui/",
-            apk: "skin_packs/persona/" -> pack: "persona/",
-            apk: "renderer/" -> pack: "renderer/",
-            apk: "resource_packs/vanilla/cameras/" -> pack: "vanilla_cameras/",
-        };
-        for replacement in replacement_list {
-            // Remove the prefix we want to change
-            if let Ok(file) = stripped.strip_prefix(replacement.0) {
-                let mut resource_loc = ResourceLocation::new();
-                let mut cpppath = ResourceLocation::get_path(&mut resource_loc);
-                opt_path_join(cpppath.as_mut(), &[Path::new(replacement.1), file]);
-                let packm = crate::PACKM_OBJ.lock().ignore_poison();
-                let Some(packm) = packm.as_ref() else {
-                    log::error!("ResourcePackManager ptr is null");
-                    return None;
-                };
-                let Some(stack_str) = packm.load_resource(resource_loc) else {
-                    log::info!("Cannot find file: {}", cpppath.as_ref());
-                    return None;
-                };
-                log::info!("Loaded ResourcePack file: {}", cpppath.as_ref());
-                let buffer = BufferCursor::Cxx(Cursor::new(stack_str));
-                let cache = Buffer::new(path.to_path_buf(), buffer);
-                // ResourceLocation gets dropped (also cxx_storage if its not needed)
-                return Some(cache);
+        let generation = source::sources_generation();
+        if generation != self.sources_generation {
+            self.sources = source::build_chain(&OPTS.lock().ignore_poison());
+            self.sources_generation = generation;
+        }
+        self.cache.sync((packs_generation(), generation));
+        if let Some(mut cache) = self.cache.take(path) {
+            // A buffer that can't be rewound is dropped and the file looked up again
+            match cache.rewind() {
+                Ok(()) => return Some(cache),
+                Err(e) => log::warn!("Dropping cached {}: {e}", path.display()),
             }
         }
-        None
+        if self.cache.is_missing(path) {
+            return None;
+        }
+        let found = self.sources.iter_mut().find_map(|source| {
+            let cacheable = source.cacheable();
+            source.lookup(stripped).map(|buffer| (buffer, cacheable))
+        });
+        let Some((mut buffer, cacheable)) = found else {
+            // Files from uncached sources can show up at any time
+            if self.sources.iter().all(|source| source.cacheable()) {
+                self.cache.mark_missing(path);
+            }
+            return None;
+        };
+        buffer.cacheable = cacheable;
+        if stripped.as_os_str().as_bytes().ends_with(b".material.bin") {
+            buffer.object = self.autofix(buffer.object, vanilla);
+        }
+        // The game knows the file by its full apk path
+        buffer.name = path.to_path_buf();
+        Some(buffer)
+    }
+    /// Give back the buffer of a closed asset so it can be reused
+    pub fn return_buffer(&mut self, buffer: Buffer) {
+        self.cache.insert(buffer);
+    }
+    /// Update a pack material to the format of the running game if needed
+    fn autofix<'a>(
+        &mut self,
+        data: BufferCursor,
+        vanilla: impl FnOnce() -> Option<&'a [u8]>,
+    ) -> BufferCursor {
+        let opts = OPTS.lock().ignore_poison();
+        if opts.autofixer_versions.is_empty() {
+            return data;
+        }
+        if self.game_version.is_none() {
+            // Guessing from the vanilla file is only needed when the game version is unknown
+            self.game_version = detect::game_version()
+                .and_then(MinecraftVersion::for_game)
+                .or_else(|| {
+                    vanilla().and_then(|vanilla| autofix::detect_version(vanilla, &ALL_VERSIONS))
+                });
+            log::info!("Game material version: {:?}", self.game_version);
+        }
+        let Some(target) = self.game_version else {
+            return data;
+        };
+        match autofix::autofix_material(data.get_ref(), target, &opts) {
+            Some(fixed) => BufferCursor::Vec(Cursor::new(fixed)),
+            None => data,
+        }
     }
 }
 pub struct Buffer {
-    name: PathBuf,
+    pub name: PathBuf,
     object: BufferCursor,
+    /// Whether the buffer can be kept after its asset is closed
+    pub cacheable: bool,
 }
 impl Buffer {
-    fn new(name: PathBuf, object: BufferCursor) -> Self {
-        Self { name, object }
+    pub fn new(name: PathBuf, object: BufferCursor) -> Self {
+        Self {
+            name,
+            object,
+            cacheable: true,
+        }
     }
 }
 impl Deref for Buffer {
@@ -128,7 +187,7 @@ impl DerefMut for Buffer {
 // This lint is not really applicable
 #[allow(clippy::unused_io_amount)]
 /// Join paths directly into a c++ string
-fn opt_path_join(mut bytes: Pin<&mut CxxString>, paths: &[&Path]) {
+pub fn opt_path_join(mut bytes: Pin<&mut CxxString>, paths: &[&Path]) {
     let total_len: usize = paths.iter().map(|p| p.as_os_str().len()).sum();
     bytes.as_mut().reserve(total_len);
     let mut writer = bytes;
@@ -139,12 +198,18 @@ fn opt_path_join(mut bytes: Pin<&mut CxxString>, paths: &[&Path]) {
             .expect("Error while writing path to stack path");
     }
 }
+// Bumped every time a ResourcePackManager gets wrapped, cached files may be stale after that
+static PACKS_GENERATION: AtomicUsize = AtomicUsize::new(0);
+pub fn packs_generation() -> usize {
+    PACKS_GENERATION.load(Ordering::Acquire)
+}
 pub struct ResourcePackManager(*mut libc::c_void);
 // Technically we can pass this everywhere as its just a handle basically
 //unsafe impl Sync for ResourcePackManager {}
 unsafe impl Send for ResourcePackManager {}
 impl ResourcePackManager {
     pub fn wrap(ptr: *mut libc::c_void) -> Self {
+        PACKS_GENERATION.fetch_add(1, Ordering::Release);
         Self(ptr)
     }
     pub fn load_resource(&self, loc: ResourceLocation) -> Option<StackString> {
@@ -169,3 +234,67 @@ impl ResourcePackManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::MemorySource;
+
+    fn loader(sources: Vec<MemorySource>) -> FileLoader {
+        let sources = sources
+            .into_iter()
+            .map(|source| Box::new(source) as Box<dyn AssetSource>)
+            .collect();
+        FileLoader::with_sources(sources)
+    }
+
+    fn read(loader: &mut FileLoader, path: &str) -> Option<Vec<u8>> {
+        let mut buffer = loader.get_file(Path::new(path), || None)?;
+        let mut data = Vec::new();
+        buffer.read_to_end(&mut data).expect("memory read");
+        loader.return_buffer(buffer);
+        Some(data)
+    }
+
+    #[test]
+    fn first_source_with_the_file_wins() {
+        let mut loader = loader(vec![
+            MemorySource::new("first").with("texts/en_US.lang", b"first"),
+            MemorySource::new("second")
+                .with("texts/en_US.lang", b"second")
+                .with("texts/de_DE.lang", b"second"),
+        ]);
+        assert_eq!(
+            read(&mut loader, "assets/texts/en_US.lang").as_deref(),
+            Some(b"first".as_slice())
+        );
+        assert_eq!(
+            read(&mut loader, "assets/texts/de_DE.lang").as_deref(),
+            Some(b"second".as_slice())
+        );
+        assert_eq!(read(&mut loader, "assets/texts/fr_FR.lang"), None);
+    }
+
+    #[test]
+    fn buffers_keep_the_apk_path() {
+        let mut loader = loader(vec![MemorySource::new("memory").with("a.json", b"{}")]);
+        let buffer = loader
+            .get_file(Path::new("assets/a.json"), || None)
+            .expect("found");
+        assert_eq!(buffer.name, Path::new("assets/a.json"));
+    }
+
+    #[test]
+    fn returned_buffers_are_served_from_the_start() {
+        let mut loader = loader(vec![MemorySource::new("memory").with("a.json", b"{}")]);
+        let mut buffer = loader
+            .get_file(Path::new("assets/a.json"), || None)
+            .expect("found");
+        buffer.seek(io::SeekFrom::End(0)).expect("memory seek");
+        loader.return_buffer(buffer);
+        assert_eq!(
+            read(&mut loader, "assets/a.json").as_deref(),
+            Some(b"{}".as_slice())
+        );
+    }
+}
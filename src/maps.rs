@@ -0,0 +1,302 @@
+// Parser for /proc/self/maps, which lists every mapping of the process
+use atoi::{FromRadix10Checked, FromRadix16Checked};
+use bstr::ByteSlice;
+use std::{ffi::OsStr, fs, io, ops::Range, os::unix::ffi::OsStrExt, path::PathBuf};
+
+const DELETED_SUFFIX: &[u8] = b" (deleted)";
+
+/// The permission column, like `r-xp`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Perms {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    /// `s`, the mapping is shared instead of private copy on write
+    pub shared: bool,
+}
+
+impl Perms {
+    pub const EXECUTE: Self = Self {
+        read: false,
+        write: false,
+        execute: true,
+        shared: false,
+    };
+    pub fn parse(text: &[u8]) -> Option<Self> {
+        let &[read, write, execute, shared] = text else {
+            return None;
+        };
+        Some(Self {
+            read: read == b'r',
+            write: write == b'w',
+            execute: execute == b'x',
+            shared: shared == b's',
+        })
+    }
+    /// Check that every permission set in `required` is set here too
+    pub fn contains(self, required: Self) -> bool {
+        (self.read || !required.read)
+            && (self.write || !required.write)
+            && (self.execute || !required.execute)
+            && (self.shared || !required.shared)
+    }
+}
+
+/// A line of the maps file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapsEntry {
+    pub range: Range<usize>,
+    pub perms: Perms,
+    /// Where in the file the range was mapped from
+    pub offset: usize,
+    /// Major and minor number of the device the file is on
+    pub dev: (u32, u32),
+    pub inode: u64,
+    /// Empty for anonymous mappings, `[stack]` and the like for special ones
+    pub pathname: PathBuf,
+    /// The file was removed after it got mapped
+    pub deleted: bool,
+}
+
+impl MapsEntry {
+    /// Parse a line, None if it is not a valid maps line
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        let mut rest = line;
+        let (start, end) = next_field(&mut rest)?.split_once_str(b"-")?;
+        let range = hex(start)?..hex(end)?;
+        let perms = Perms::parse(next_field(&mut rest)?)?;
+        let offset = hex(next_field(&mut rest)?)?;
+        let (major, minor) = next_field(&mut rest)?.split_once_str(b":")?;
+        let dev = (hex(major)?, hex(minor)?);
+        let inode = decimal(next_field(&mut rest)?)?;
+        // Everything else is the pathname, which can have spaces in it
+        let pathname = rest.trim_start_with(|c| c == ' ' || c == '\t');
+        // Anonymous mappings end in a space, and a file read on Windows in \r
+        let pathname = pathname.trim_ascii_end();
+        let (pathname, deleted) = match pathname.strip_suffix(DELETED_SUFFIX) {
+            Some(pathname) => (pathname, true),
+            None => (pathname, false),
+        };
+        if range.start > range.end {
+            return None;
+        }
+        Some(Self {
+            range,
+            perms,
+            offset,
+            dev,
+            inode,
+            pathname: PathBuf::from(OsStr::from_bytes(pathname)),
+            deleted,
+        })
+    }
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+    pub fn size(&self) -> usize {
+        self.range.end - self.range.start
+    }
+    pub fn contains(&self, address: usize) -> bool {
+        self.range.contains(&address)
+    }
+    /// Check the file name of a mapped file, `libc.so` matches `/apex/.../libc.so`
+    pub fn is_named(&self, name: &str) -> bool {
+        self.pathname.file_name() == Some(OsStr::new(name))
+    }
+}
+
+/// Every mapping of a process, in address order
+#[derive(Debug, Clone, Default)]
+pub struct Maps {
+    pub entries: Vec<MapsEntry>,
+}
+
+impl Maps {
+    /// The mappings of our own process
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn read_self() -> io::Result<Self> {
+        fs::read("/proc/self/maps").map(|contents| Self::parse(&contents))
+    }
+    /// Parse a whole maps file, lines that can't be parsed are skipped
+    pub fn parse(contents: &[u8]) -> Self {
+        let entries = contents
+            .lines()
+            .filter(|line| !line.trim_ascii().is_empty())
+            .filter_map(MapsEntry::parse)
+            .collect();
+        Self { entries }
+    }
+    /// Mappings of a file, by its file name
+    pub fn by_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a MapsEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.is_named(name))
+    }
+    /// Mappings that have at least the `required` permissions
+    pub fn by_perms(&self, required: Perms) -> impl Iterator<Item = &MapsEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.perms.contains(required))
+    }
+    /// The mapping an address is in
+    pub fn find_address(&self, address: usize) -> Option<&MapsEntry> {
+        self.entries.iter().find(|entry| entry.contains(address))
+    }
+}
+
+/// Split off the next space separated field
+fn next_field<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let trimmed = rest.trim_start_with(|c| c == ' ' || c == '\t');
+    let end = trimmed.find_byteset(b" \t").unwrap_or(trimmed.len());
+    let (field, after) = trimmed.split_at(end);
+    *rest = after;
+    (!field.is_empty()).then_some(field)
+}
+
+fn hex<T: FromRadix16Checked>(text: &[u8]) -> Option<T> {
+    match T::from_radix_16_checked(text) {
+        (value, used) if used == text.len() && used > 0 => value,
+        _ => None,
+    }
+}
+
+fn decimal<T: FromRadix10Checked>(text: &[u8]) -> Option<T> {
+    match T::from_radix_10_checked(text) {
+        (value, used) if used == text.len() && used > 0 => value,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_fixture(name: &str) -> Vec<u8> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/maps")
+            .join(name);
+        fs::read(path).expect("maps fixture")
+    }
+
+    #[test]
+    fn arm64_maps() {
+        let maps = Maps::parse(&read_fixture("arm64.maps"));
+        assert_eq!(maps.entries.len(), 13);
+        assert_eq!(maps.by_name("libminecraftpe.so").count(), 3);
+        let code: Vec<_> = maps
+            .by_perms(Perms::EXECUTE)
+            .filter(|entry| entry.is_named("libminecraftpe.so"))
+            .collect();
+        let [code] = code.as_slice() else {
+            panic!("one code mapping, found {code:?}");
+        };
+        assert_eq!(code.start(), 0x7a1e3c4000);
+        assert_eq!(code.size(), 0x7058000);
+        assert_eq!(code.offset, 0x23c4000);
+        assert_eq!(code.dev, (0xfe, 0x21));
+        assert_eq!(code.inode, 303);
+    }
+
+    #[test]
+    fn paths_with_spaces_and_deleted_files() {
+        let maps = Maps::parse(&read_fixture("arm64.maps"));
+        let entry = maps.find_address(0x7b00000800).expect("mapped");
+        assert!(entry.deleted);
+        assert!(entry.is_named("libmod.so"));
+        assert_eq!(
+            entry.pathname.as_os_str(),
+            "/data/user/0/io.bambosan.mbloader/files/My Mods/libmod.so"
+        );
+    }
+
+    #[test]
+    fn anonymous_and_special_mappings() {
+        let maps = Maps::parse(&read_fixture("arm64.maps"));
+        let anonymous = maps.find_address(0x7fc9a21000).expect("mapped");
+        assert!(anonymous.pathname.as_os_str().is_empty());
+        for (address, name) in [
+            (0x7a25a1c000, "[anon:.bss]"),
+            (0x7b10000000, "[anon:scudo:primary]"),
+            (0x7b20000000, "[anon:dalvik-LinearAlloc]"),
+        ] {
+            let entry = maps.find_address(address).expect("mapped");
+            assert_eq!(entry.pathname.as_os_str(), name);
+            assert!(!entry.deleted);
+        }
+        let stack = maps.find_address(0x7fc9a00000).expect("mapped");
+        assert_eq!(stack.pathname.as_os_str(), "[stack]");
+        assert!(stack.perms.write && !stack.perms.execute);
+        // The end of a range is exclusive
+        assert_eq!(maps.find_address(0x7fc9a22000), None);
+    }
+
+    #[test]
+    fn arm_maps() {
+        let maps = Maps::parse(&read_fixture("arm.maps"));
+        let code: Vec<_> = maps
+            .by_name("libminecraftpe.so")
+            .filter(|entry| entry.perms.execute)
+            .map(|entry| (entry.start(), entry.offset))
+            .collect();
+        assert_eq!(code, [(0xc2a00000, 0x1600000)]);
+        let shared = maps.find_address(0xe8d00000).expect("mapped");
+        assert!(shared.perms.shared && shared.deleted);
+        let apk = maps.find_address(0xd0010000).expect("mapped");
+        assert!(apk.is_named("base.apk") && apk.perms.execute);
+        assert_eq!(apk.offset, 0x62a000);
+        let malloc = maps.find_address(0xe9000000).expect("mapped");
+        assert_eq!(malloc.pathname.as_os_str(), "[anon:libc_malloc]");
+    }
+
+    #[test]
+    fn libraries_mapped_from_the_apk() {
+        let maps = Maps::parse(&read_fixture("arm64.maps"));
+        let code = maps.find_address(0x7a30012000).expect("mapped");
+        assert!(code.is_named("base.apk") && code.perms.execute);
+        assert_eq!(code.offset, 0xa5e000);
+        assert_eq!(code.inode, 298);
+        assert_eq!(maps.by_name("base.apk").count(), 3);
+        assert!(maps
+            .by_name("libminecraftpe.so")
+            .all(|entry| !entry.is_named("base.apk")));
+    }
+
+    #[test]
+    fn x86_64_maps() {
+        let data = read_fixture("x86_64.maps");
+        let maps = Maps::parse(&data);
+        assert_eq!(maps.entries.len(), data.lines().count());
+        let code: Vec<_> = maps
+            .by_name("libc.so.6")
+            .filter(|entry| entry.perms.execute)
+            .map(|entry| entry.offset)
+            .collect();
+        assert_eq!(code, [0x26000]);
+        let vsyscall = maps.find_address(0xffffffffff600000).expect("mapped");
+        assert_eq!(vsyscall.pathname.as_os_str(), "[vsyscall]");
+        assert!(vsyscall.perms.execute && !vsyscall.perms.read);
+        let anonymous = maps.find_address(0x7f4f59095000).expect("mapped");
+        assert!(anonymous.pathname.as_os_str().is_empty());
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let maps = Maps::parse(
+            b"not a maps line\n7000-6000 r--p 00000000 00:00 0\n7000-8000 r--p 0 00:00 0\n",
+        );
+        assert_eq!(maps.entries.len(), 1);
+    }
+
+    #[test]
+    fn line_ends_are_not_part_of_the_path() {
+        let entry = MapsEntry::parse(
+            b"c2a00000-c6600000 r-xp 01600000 fe:21 517        /data/local/tmp/libminecraftpe.so (deleted)\r",
+        )
+        .expect("valid line");
+        assert!(entry.deleted);
+        assert_eq!(
+            entry.pathname.as_os_str(),
+            "/data/local/tmp/libminecraftpe.so"
+        );
+    }
+}
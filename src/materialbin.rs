@@ -0,0 +1,430 @@
+// Reader and writer for RenderDragon .material.bin files
+mod bgfx;
+mod pass;
+mod sampler;
+
+pub use bgfx::BgfxShader;
+pub use pass::Pass;
+pub use sampler::{PropertyField, SamplerDefinition};
+
+use crate::version::GameVersion;
+use scroll::{ctx::TryFromCtx, Pread, LE};
+use std::io::{self, Write};
+
+const MAGIC: u64 = 0xA11DA1A;
+const DEFINITION_NAME: &str = "RenderDragon.CompiledMaterialDefinition";
+// Encryption is stored as a fourcc
+const ENCRYPTION_NONE: u32 = u32::from_le_bytes(*b"NONE");
+// 26.x kept the 1.21.110 layout, only the definition version in the header tells them apart
+const V26_DEFINITION_VERSION: u64 = 23;
+
+/// Material format revisions, ordered from oldest to newest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MinecraftVersion {
+    V1_18_30,
+    V1_19_60,
+    V1_20_80,
+    V1_21_20,
+    V1_21_110,
+    V26_0_24,
+}
+impl MinecraftVersion {
+    /// The game release that introduced this format
+    pub const fn release(self) -> GameVersion {
+        match self {
+            Self::V1_18_30 => GameVersion::new(1, 18, 30, 0),
+            Self::V1_19_60 => GameVersion::new(1, 19, 60, 0),
+            Self::V1_20_80 => GameVersion::new(1, 20, 80, 0),
+            Self::V1_21_20 => GameVersion::new(1, 21, 20, 0),
+            Self::V1_21_110 => GameVersion::new(1, 21, 110, 0),
+            Self::V26_0_24 => GameVersion::new(26, 0, 24, 0),
+        }
+    }
+    /// The format a game release reads, None if it is older than every format we know
+    pub fn for_game(version: GameVersion) -> Option<Self> {
+        ALL_VERSIONS
+            .into_iter()
+            .find(|format| format.release() <= version)
+    }
+    /// The definition version to put in the header of a material written in this format
+    fn definition_version(self, version: u64) -> u64 {
+        match self {
+            Self::V26_0_24 => version.max(V26_DEFINITION_VERSION),
+            _ => version.min(V26_DEFINITION_VERSION - 1),
+        }
+    }
+}
+/// Every supported format, newest first so recent packs are found faster
+pub const ALL_VERSIONS: [MinecraftVersion; 6] = [
+    MinecraftVersion::V26_0_24,
+    MinecraftVersion::V1_21_110,
+    MinecraftVersion::V1_21_20,
+    MinecraftVersion::V1_20_80,
+    MinecraftVersion::V1_19_60,
+    MinecraftVersion::V1_18_30,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledMaterialDefinition {
+    /// Written as at least 23 in the v26.0.24 format and below that in older ones
+    pub version: u64,
+    pub name: String,
+    pub parent_name: Option<String>,
+    pub sampler_definitions: Vec<(String, SamplerDefinition)>,
+    pub property_fields: Vec<(String, PropertyField)>,
+    /// Only present since v1.21.20
+    pub uniform_overrides: Vec<(String, String)>,
+    pub passes: Vec<(String, Pass)>,
+}
+
+impl<'a> TryFromCtx<'a, MinecraftVersion> for CompiledMaterialDefinition {
+    type Error = scroll::Error;
+    fn try_from_ctx(
+        src: &'a [u8],
+        version: MinecraftVersion,
+    ) -> Result<(Self, usize), Self::Error> {
+        let offset = &mut 0;
+        let magic: u64 = src.gread_with(offset, LE)?;
+        if magic != MAGIC {
+            return Err(bad_input("Invalid material magic"));
+        }
+        if read_string(src, offset)? != DEFINITION_NAME {
+            return Err(bad_input("Not a compiled material definition"));
+        }
+        let def_version: u64 = src.gread_with(offset, LE)?;
+        if version.definition_version(def_version) != def_version {
+            return Err(bad_input("Definition version belongs to another format"));
+        }
+        let encryption: u32 = src.gread_with(offset, LE)?;
+        if encryption != ENCRYPTION_NONE {
+            return Err(bad_input("Encrypted materials are not supported"));
+        }
+        let name = read_string(src, offset)?;
+        let parent_name = read_option(src, offset, read_string)?;
+        let sampler_count: u8 = src.gread_with(offset, LE)?;
+        let sampler_definitions = read_list(src, offset, sampler_count.into(), |src, offset| {
+            Ok((
+                read_string(src, offset)?,
+                SamplerDefinition::read(src, offset, version)?,
+            ))
+        })?;
+        let property_count: u16 = src.gread_with(offset, LE)?;
+        let property_fields = read_list(src, offset, property_count.into(), |src, offset| {
+            Ok((read_string(src, offset)?, PropertyField::read(src, offset)?))
+        })?;
+        let uniform_overrides = if version >= MinecraftVersion::V1_21_20 {
+            let count: u16 = src.gread_with(offset, LE)?;
+            read_list(src, offset, count.into(), read_string_pair)?
+        } else {
+            Vec::new()
+        };
+        let pass_count: u16 = src.gread_with(offset, LE)?;
+        let passes = read_list(src, offset, pass_count.into(), |src, offset| {
+            Ok((read_string(src, offset)?, Pass::read(src, offset, version)?))
+        })?;
+        let end_magic: u64 = src.gread_with(offset, LE)?;
+        if end_magic != MAGIC {
+            return Err(bad_input("Invalid material end magic"));
+        }
+        let material = Self {
+            version: def_version,
+            name,
+            parent_name,
+            sampler_definitions,
+            property_fields,
+            uniform_overrides,
+            passes,
+        };
+        Ok((material, *offset))
+    }
+}
+
+impl CompiledMaterialDefinition {
+    /// Serialize the material in the format of `version`
+    pub fn write<W: Write>(&self, writer: &mut W, version: MinecraftVersion) -> io::Result<()> {
+        writer.write_all(&MAGIC.to_le_bytes())?;
+        write_string(writer, DEFINITION_NAME)?;
+        let def_version = version.definition_version(self.version);
+        writer.write_all(&def_version.to_le_bytes())?;
+        writer.write_all(&ENCRYPTION_NONE.to_le_bytes())?;
+        write_string(writer, &self.name)?;
+        write_option(writer, self.parent_name.as_deref(), write_string)?;
+        write_len::<u8>(writer, self.sampler_definitions.len())?;
+        for (name, sampler) in &self.sampler_definitions {
+            write_string(writer, name)?;
+            sampler.write(writer, version)?;
+        }
+        write_len::<u16>(writer, self.property_fields.len())?;
+        for (name, field) in &self.property_fields {
+            write_string(writer, name)?;
+            field.write(writer)?;
+        }
+        if version >= MinecraftVersion::V1_21_20 {
+            write_len::<u16>(writer, self.uniform_overrides.len())?;
+            for pair in &self.uniform_overrides {
+                write_string_pair(writer, pair)?;
+            }
+        }
+        write_len::<u16>(writer, self.passes.len())?;
+        for (name, pass) in &self.passes {
+            write_string(writer, name)?;
+            pass.write(writer, version)?;
+        }
+        writer.write_all(&MAGIC.to_le_bytes())
+    }
+}
+
+fn bad_input(msg: &'static str) -> scroll::Error {
+    scroll::Error::BadInput { size: 0, msg }
+}
+
+fn read_bool(src: &[u8], offset: &mut usize) -> Result<bool, scroll::Error> {
+    let value: u8 = src.gread_with(offset, LE)?;
+    Ok(value != 0)
+}
+
+fn read_string(src: &[u8], offset: &mut usize) -> Result<String, scroll::Error> {
+    let bytes = read_bytes(src, offset)?;
+    String::from_utf8(bytes).map_err(|_| bad_input("String is not valid utf8"))
+}
+
+fn read_bytes(src: &[u8], offset: &mut usize) -> Result<Vec<u8>, scroll::Error> {
+    let len: u32 = src.gread_with(offset, LE)?;
+    let len = usize::try_from(len).map_err(|_| bad_input("Length does not fit in memory"))?;
+    let end = offset
+        .checked_add(len)
+        .ok_or(bad_input("Length overflows buffer"))?;
+    let bytes = src.get(*offset..end).ok_or(scroll::Error::TooBig {
+        size: len,
+        len: src.len(),
+    })?;
+    *offset = end;
+    Ok(bytes.to_vec())
+}
+
+fn read_string_pair(src: &[u8], offset: &mut usize) -> Result<(String, String), scroll::Error> {
+    Ok((read_string(src, offset)?, read_string(src, offset)?))
+}
+
+fn read_option<T>(
+    src: &[u8],
+    offset: &mut usize,
+    read: impl FnOnce(&[u8], &mut usize) -> Result<T, scroll::Error>,
+) -> Result<Option<T>, scroll::Error> {
+    match read_bool(src, offset)? {
+        true => Ok(Some(read(src, offset)?)),
+        false => Ok(None),
+    }
+}
+
+fn read_list<T>(
+    src: &[u8],
+    offset: &mut usize,
+    count: usize,
+    mut read: impl FnMut(&[u8], &mut usize) -> Result<T, scroll::Error>,
+) -> Result<Vec<T>, scroll::Error> {
+    // Don't trust the count for the allocation, a broken file could ask for anything
+    let mut list = Vec::with_capacity(count.min(64));
+    for _ in 0..count {
+        list.push(read(src, offset)?);
+    }
+    Ok(list)
+}
+
+fn write_bool<W: Write>(writer: &mut W, value: bool) -> io::Result<()> {
+    writer.write_all(&[value.into()])
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    write_bytes(writer, string.as_bytes())
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_len::<u32>(writer, bytes.len())?;
+    writer.write_all(bytes)
+}
+
+fn write_string_pair<W: Write>(writer: &mut W, pair: &(String, String)) -> io::Result<()> {
+    write_string(writer, &pair.0)?;
+    write_string(writer, &pair.1)
+}
+
+fn write_option<W: Write, T>(
+    writer: &mut W,
+    value: Option<T>,
+    write: impl FnOnce(&mut W, T) -> io::Result<()>,
+) -> io::Result<()> {
+    write_bool(writer, value.is_some())?;
+    match value {
+        Some(value) => write(writer, value),
+        None => Ok(()),
+    }
+}
+
+/// Write a collection length as the integer type the format uses for it
+fn write_len<T>(writer: &mut impl Write, len: usize) -> io::Result<()>
+where
+    T: TryFrom<usize> + LeBytes,
+{
+    let len = T::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Collection too long"))?;
+    writer.write_all(len.le_bytes().as_ref())
+}
+
+trait LeBytes {
+    type Bytes: AsRef<[u8]>;
+    fn le_bytes(self) -> Self::Bytes;
+}
+macro_rules! impl_le_bytes {
+    ($($int:ty),*) => {
+        $(impl LeBytes for $int {
+            type Bytes = [u8; std::mem::size_of::<$int>()];
+            fn le_bytes(self) -> Self::Bytes {
+                self.to_le_bytes()
+            }
+        })*
+    };
+}
+impl_le_bytes!(u8, u16, u32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    /// Fixtures live in a folder named after the release that introduced their format
+    fn fixtures() -> Vec<(MinecraftVersion, PathBuf)> {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/materialbin");
+        let mut fixtures = Vec::new();
+        for dir in fs::read_dir(&root).expect("fixture folder") {
+            let dir = dir.expect("fixture folder entry").path();
+            if !dir.is_dir() {
+                continue;
+            }
+            let release: GameVersion = dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse().ok())
+                .expect("fixture folders are named after a release");
+            let version = ALL_VERSIONS
+                .into_iter()
+                .find(|version| version.release() == release)
+                .expect("fixture folders are named after a format");
+            for file in fs::read_dir(&dir).expect("fixture folder") {
+                let path = file.expect("fixture file").path();
+                if path.to_string_lossy().ends_with(".material.bin") {
+                    fixtures.push((version, path));
+                }
+            }
+        }
+        fixtures
+    }
+
+    #[test]
+    fn every_format_has_a_fixture() {
+        let fixtures = fixtures();
+        for version in ALL_VERSIONS {
+            assert!(
+                fixtures.iter().any(|(fixture, _)| *fixture == version),
+                "No fixture for {version:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn write_gives_back_the_parsed_file() {
+        for (version, path) in fixtures() {
+            let data = fs::read(&path).expect("fixture");
+            let offset = &mut 0;
+            let material: CompiledMaterialDefinition = data
+                .gread_with(offset, version)
+                .unwrap_or_else(|e| panic!("Cannot parse {}: {e}", path.display()));
+            assert_eq!(*offset, data.len(), "{} has trailing data", path.display());
+            let mut output = Vec::new();
+            material.write(&mut output, version).expect("write");
+            assert!(output == data, "{} changed on write", path.display());
+        }
+    }
+
+    #[test]
+    fn shaders_are_kept_as_is() {
+        for (version, path) in fixtures() {
+            let data = fs::read(&path).expect("fixture");
+            let material: CompiledMaterialDefinition =
+                data.pread_with(0, version).expect("fixture parses");
+            let codes = material
+                .passes
+                .iter()
+                .flat_map(|(_, pass)| &pass.variants)
+                .flat_map(|variant| &variant.shader_codes);
+            for (_, code) in codes {
+                let shader = BgfxShader::read(&code.bgfx_shader_data).expect("bgfx shader");
+                assert_eq!(shader.write(), Some(code.bgfx_shader_data.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn other_formats_do_not_misread() {
+        for (version, path) in fixtures() {
+            let data = fs::read(&path).expect("fixture");
+            for other in ALL_VERSIONS.into_iter().filter(|other| *other != version) {
+                let read = data.pread_with::<CompiledMaterialDefinition>(0, other);
+                assert!(read.is_err(), "{} misread as {other:?}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn writing_in_another_format_updates_the_definition_version() {
+        let read = |release: &str, version| {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures/materialbin")
+                .join(release)
+                .join("Fixture.material.bin");
+            let data = fs::read(path).expect("fixture");
+            data.pread_with::<CompiledMaterialDefinition>(0, version)
+                .expect("fixture parses")
+        };
+        for (from, to, release) in [
+            (
+                read("1.21.110", MinecraftVersion::V1_21_110),
+                MinecraftVersion::V26_0_24,
+                "26.0.24",
+            ),
+            (
+                read("26.0.24", MinecraftVersion::V26_0_24),
+                MinecraftVersion::V1_21_110,
+                "1.21.110",
+            ),
+        ] {
+            let mut output = Vec::new();
+            from.write(&mut output, to).expect("write");
+            let material: CompiledMaterialDefinition =
+                output.pread_with(0, to).expect("written material parses");
+            assert_eq!(material, read(release, to));
+        }
+    }
+
+    #[test]
+    fn games_read_the_newest_older_format() {
+        let game = |text: &str| text.parse::<GameVersion>().expect("version");
+        assert_eq!(MinecraftVersion::for_game(game("1.18.12")), None);
+        assert_eq!(
+            MinecraftVersion::for_game(game("1.18.30")),
+            Some(MinecraftVersion::V1_18_30)
+        );
+        assert_eq!(
+            MinecraftVersion::for_game(game("1.21.100.6")),
+            Some(MinecraftVersion::V1_21_20)
+        );
+        assert_eq!(
+            MinecraftVersion::for_game(game("1.21.130")),
+            Some(MinecraftVersion::V1_21_110)
+        );
+        assert_eq!(
+            MinecraftVersion::for_game(game("26.10")),
+            Some(MinecraftVersion::V26_0_24)
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use super::bad_input;
+use scroll::{Pread, LE};
+
+/// A compiled bgfx shader, only the source code is parsed out
+#[derive(Debug, Clone, PartialEq)]
+pub struct BgfxShader {
+    /// Header and uniform table, kept as is
+    pub header: Vec<u8>,
+    /// Shader source for glsl/essl, bytecode for everything else
+    pub code: Vec<u8>,
+    /// Everything after the code (attributes, constant buffer size)
+    pub trailer: Vec<u8>,
+}
+
+impl BgfxShader {
+    pub fn read(src: &[u8]) -> Result<Self, scroll::Error> {
+        let offset = &mut 0;
+        let magic: [u8; 4] = [
+            src.gread(offset)?,
+            src.gread(offset)?,
+            src.gread(offset)?,
+            src.gread(offset)?,
+        ];
+        let [kind @ .., shader_version] = magic;
+        if !matches!(&kind, b"VSH" | b"FSH" | b"CSH") {
+            return Err(bad_input("Invalid bgfx shader magic"));
+        }
+        // Input hash, versions 6+ also store the output hash
+        let _hash_in: u32 = src.gread_with(offset, LE)?;
+        if shader_version >= 6 {
+            let _hash_out: u32 = src.gread_with(offset, LE)?;
+        }
+        let uniform_count: u16 = src.gread_with(offset, LE)?;
+        for _ in 0..uniform_count {
+            let name_len: u8 = src.gread_with(offset, LE)?;
+            *offset += usize::from(name_len);
+            // type, num, register index and register count
+            *offset += 1 + 1 + 2 + 2;
+            if shader_version >= 8 {
+                // texture info
+                *offset += 2;
+            }
+            if shader_version >= 10 {
+                // texture format
+                *offset += 2;
+            }
+        }
+        let header = src
+            .get(..*offset)
+            .ok_or(bad_input("Bgfx uniform table is truncated"))?;
+        let code_len: u32 = src.gread_with(offset, LE)?;
+        let code_end = offset
+            .checked_add(code_len as usize)
+            .ok_or(bad_input("Bgfx code length overflows"))?;
+        let code = src
+            .get(*offset..code_end)
+            .ok_or(bad_input("Bgfx code is truncated"))?;
+        let trailer = src.get(code_end..).unwrap_or_default();
+        Ok(Self {
+            header: header.to_vec(),
+            code: code.to_vec(),
+            trailer: trailer.to_vec(),
+        })
+    }
+    pub fn write(&self) -> Option<Vec<u8>> {
+        let code_len = u32::try_from(self.code.len()).ok()?;
+        let mut output =
+            Vec::with_capacity(self.header.len() + 4 + self.code.len() + self.trailer.len());
+        output.extend_from_slice(&self.header);
+        output.extend_from_slice(&code_len.to_le_bytes());
+        output.extend_from_slice(&self.code);
+        output.extend_from_slice(&self.trailer);
+        Some(output)
+    }
+}
@@ -0,0 +1,239 @@
+use super::{
+    read_bool, read_bytes, read_list, read_option, read_string, read_string_pair, write_bool,
+    write_bytes, write_len, write_option, write_string, write_string_pair, MinecraftVersion,
+};
+use scroll::{Pread, LE};
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pass {
+    /// Which platforms this pass supports, as a string of 0s and 1s
+    pub bitset: String,
+    pub fallback_pass: String,
+    pub default_blendmode: Option<u16>,
+    pub default_variant: Vec<(String, String)>,
+    /// Only present since v1.20.80
+    pub framebuffer_binding: u8,
+    pub variants: Vec<Variant>,
+}
+
+impl Pass {
+    pub(super) fn read(
+        src: &[u8],
+        offset: &mut usize,
+        version: MinecraftVersion,
+    ) -> Result<Self, scroll::Error> {
+        let bitset = read_string(src, offset)?;
+        let fallback_pass = read_string(src, offset)?;
+        let default_blendmode = read_option(src, offset, |src, offset| src.gread_with(offset, LE))?;
+        let default_count: u16 = src.gread_with(offset, LE)?;
+        let default_variant = read_list(src, offset, default_count.into(), read_string_pair)?;
+        let framebuffer_binding = if version >= MinecraftVersion::V1_20_80 {
+            src.gread_with(offset, LE)?
+        } else {
+            0
+        };
+        let variant_count: u16 = src.gread_with(offset, LE)?;
+        let variants = read_list(src, offset, variant_count.into(), |src, offset| {
+            Variant::read(src, offset, version)
+        })?;
+        Ok(Self {
+            bitset,
+            fallback_pass,
+            default_blendmode,
+            default_variant,
+            framebuffer_binding,
+            variants,
+        })
+    }
+    pub(super) fn write<W: Write>(
+        &self,
+        writer: &mut W,
+        version: MinecraftVersion,
+    ) -> io::Result<()> {
+        write_string(writer, &self.bitset)?;
+        write_string(writer, &self.fallback_pass)?;
+        write_option(writer, self.default_blendmode, |writer, mode| {
+            writer.write_all(&mode.to_le_bytes())
+        })?;
+        write_len::<u16>(writer, self.default_variant.len())?;
+        for pair in &self.default_variant {
+            write_string_pair(writer, pair)?;
+        }
+        if version >= MinecraftVersion::V1_20_80 {
+            writer.write_all(&[self.framebuffer_binding])?;
+        }
+        write_len::<u16>(writer, self.variants.len())?;
+        for variant in &self.variants {
+            variant.write(writer, version)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub is_supported: bool,
+    pub flags: Vec<(String, String)>,
+    pub shader_codes: Vec<(PlatformShaderStage, ShaderCode)>,
+}
+
+impl Variant {
+    fn read(
+        src: &[u8],
+        offset: &mut usize,
+        version: MinecraftVersion,
+    ) -> Result<Self, scroll::Error> {
+        let is_supported = read_bool(src, offset)?;
+        let flag_count: u16 = src.gread_with(offset, LE)?;
+        let flags = read_list(src, offset, flag_count.into(), read_string_pair)?;
+        let code_count: u16 = src.gread_with(offset, LE)?;
+        let shader_codes = read_list(src, offset, code_count.into(), |src, offset| {
+            Ok((
+                PlatformShaderStage::read(src, offset)?,
+                ShaderCode::read(src, offset, version)?,
+            ))
+        })?;
+        Ok(Self {
+            is_supported,
+            flags,
+            shader_codes,
+        })
+    }
+    fn write<W: Write>(&self, writer: &mut W, version: MinecraftVersion) -> io::Result<()> {
+        write_bool(writer, self.is_supported)?;
+        write_len::<u16>(writer, self.flags.len())?;
+        for pair in &self.flags {
+            write_string_pair(writer, pair)?;
+        }
+        write_len::<u16>(writer, self.shader_codes.len())?;
+        for (stage, code) in &self.shader_codes {
+            stage.write(writer)?;
+            code.write(writer, version)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlatformShaderStage {
+    pub stage_name: String,
+    pub platform_name: String,
+    pub stage: u8,
+    pub platform: u8,
+}
+
+impl PlatformShaderStage {
+    fn read(src: &[u8], offset: &mut usize) -> Result<Self, scroll::Error> {
+        Ok(Self {
+            stage_name: read_string(src, offset)?,
+            platform_name: read_string(src, offset)?,
+            stage: src.gread_with(offset, LE)?,
+            platform: src.gread_with(offset, LE)?,
+        })
+    }
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_string(writer, &self.stage_name)?;
+        write_string(writer, &self.platform_name)?;
+        writer.write_all(&[self.stage, self.platform])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderCode {
+    pub shader_inputs: Vec<(String, ShaderInput)>,
+    pub source_hash: u64,
+    /// The compiled bgfx shader for this platform
+    pub bgfx_shader_data: Vec<u8>,
+}
+
+impl ShaderCode {
+    fn read(
+        src: &[u8],
+        offset: &mut usize,
+        version: MinecraftVersion,
+    ) -> Result<Self, scroll::Error> {
+        let input_count: u16 = src.gread_with(offset, LE)?;
+        let shader_inputs = read_list(src, offset, input_count.into(), |src, offset| {
+            Ok((
+                read_string(src, offset)?,
+                ShaderInput::read(src, offset, version)?,
+            ))
+        })?;
+        let source_hash = src.gread_with(offset, LE)?;
+        let bgfx_shader_data = read_bytes(src, offset)?;
+        Ok(Self {
+            shader_inputs,
+            source_hash,
+            bgfx_shader_data,
+        })
+    }
+    fn write<W: Write>(&self, writer: &mut W, version: MinecraftVersion) -> io::Result<()> {
+        write_len::<u16>(writer, self.shader_inputs.len())?;
+        for (name, input) in &self.shader_inputs {
+            write_string(writer, name)?;
+            input.write(writer, version)?;
+        }
+        writer.write_all(&self.source_hash.to_le_bytes())?;
+        write_bytes(writer, &self.bgfx_shader_data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderInput {
+    pub input_type: u8,
+    pub attribute_index: u8,
+    pub attribute_subindex: u8,
+    pub is_per_instance: bool,
+    /// Only present since v1.19.60
+    pub precision_constraint: Option<u8>,
+    /// Only present since v1.19.60
+    pub interpolation_constraint: Option<u8>,
+}
+
+impl ShaderInput {
+    fn read(
+        src: &[u8],
+        offset: &mut usize,
+        version: MinecraftVersion,
+    ) -> Result<Self, scroll::Error> {
+        let input_type = src.gread_with(offset, LE)?;
+        let attribute_index = src.gread_with(offset, LE)?;
+        let attribute_subindex = src.gread_with(offset, LE)?;
+        let is_per_instance = read_bool(src, offset)?;
+        let (precision_constraint, interpolation_constraint) =
+            if version >= MinecraftVersion::V1_19_60 {
+                (
+                    read_option(src, offset, |src, offset| src.gread_with(offset, LE))?,
+                    read_option(src, offset, |src, offset| src.gread_with(offset, LE))?,
+                )
+            } else {
+                (None, None)
+            };
+        Ok(Self {
+            input_type,
+            attribute_index,
+            attribute_subindex,
+            is_per_instance,
+            precision_constraint,
+            interpolation_constraint,
+        })
+    }
+    fn write<W: Write>(&self, writer: &mut W, version: MinecraftVersion) -> io::Result<()> {
+        writer.write_all(&[
+            self.input_type,
+            self.attribute_index,
+            self.attribute_subindex,
+        ])?;
+        write_bool(writer, self.is_per_instance)?;
+        if version >= MinecraftVersion::V1_19_60 {
+            write_option(writer, self.precision_constraint, |writer, value| {
+                writer.write_all(&[value])
+            })?;
+            write_option(writer, self.interpolation_constraint, |writer, value| {
+                writer.write_all(&[value])
+            })?;
+        }
+        Ok(())
+    }
+}
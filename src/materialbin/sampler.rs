@@ -0,0 +1,164 @@
+use super::{
+    bad_input, read_bool, read_option, read_string, write_bool, write_option, write_string,
+    MinecraftVersion,
+};
+use scroll::{Pread, LE};
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplerDefinition {
+    /// Stored as a u16 since v1.21.110, a u8 before that
+    pub reg: u16,
+    pub access: u8,
+    pub precision: u8,
+    pub allow_unordered_access: bool,
+    pub sampler_type: u8,
+    pub texture_format: String,
+    pub unknown_int: u32,
+    pub unknown_byte: u8,
+    pub sampler_state: Option<u8>,
+    pub default_texture: Option<String>,
+    /// Only present since v1.20.80
+    pub unknown_string: Option<String>,
+    pub custom_type_info: Option<CustomTypeInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomTypeInfo {
+    pub struct_name: String,
+    pub size: u32,
+}
+
+impl SamplerDefinition {
+    pub(super) fn read(
+        src: &[u8],
+        offset: &mut usize,
+        version: MinecraftVersion,
+    ) -> Result<Self, scroll::Error> {
+        let reg = if version >= MinecraftVersion::V1_21_110 {
+            src.gread_with::<u16>(offset, LE)?
+        } else {
+            src.gread_with::<u8>(offset, LE)?.into()
+        };
+        let access = src.gread_with(offset, LE)?;
+        let precision = src.gread_with(offset, LE)?;
+        let allow_unordered_access = read_bool(src, offset)?;
+        let sampler_type = src.gread_with(offset, LE)?;
+        let texture_format = read_string(src, offset)?;
+        let unknown_int = src.gread_with(offset, LE)?;
+        let unknown_byte = src.gread_with(offset, LE)?;
+        let sampler_state = read_option(src, offset, |src, offset| src.gread_with(offset, LE))?;
+        let default_texture = read_option(src, offset, read_string)?;
+        let unknown_string = if version >= MinecraftVersion::V1_20_80 {
+            read_option(src, offset, read_string)?
+        } else {
+            None
+        };
+        let custom_type_info = read_option(src, offset, |src, offset| {
+            Ok(CustomTypeInfo {
+                struct_name: read_string(src, offset)?,
+                size: src.gread_with(offset, LE)?,
+            })
+        })?;
+        Ok(Self {
+            reg,
+            access,
+            precision,
+            allow_unordered_access,
+            sampler_type,
+            texture_format,
+            unknown_int,
+            unknown_byte,
+            sampler_state,
+            default_texture,
+            unknown_string,
+            custom_type_info,
+        })
+    }
+    pub(super) fn write<W: Write>(
+        &self,
+        writer: &mut W,
+        version: MinecraftVersion,
+    ) -> io::Result<()> {
+        if version >= MinecraftVersion::V1_21_110 {
+            writer.write_all(&self.reg.to_le_bytes())?;
+        } else {
+            let reg = u8::try_from(self.reg).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Sampler register too big for format",
+                )
+            })?;
+            writer.write_all(&[reg])?;
+        }
+        writer.write_all(&[self.access, self.precision])?;
+        write_bool(writer, self.allow_unordered_access)?;
+        writer.write_all(&[self.sampler_type])?;
+        write_string(writer, &self.texture_format)?;
+        writer.write_all(&self.unknown_int.to_le_bytes())?;
+        writer.write_all(&[self.unknown_byte])?;
+        write_option(writer, self.sampler_state, |writer, state| {
+            writer.write_all(&[state])
+        })?;
+        write_option(writer, self.default_texture.as_deref(), write_string)?;
+        if version >= MinecraftVersion::V1_20_80 {
+            write_option(writer, self.unknown_string.as_deref(), write_string)?;
+        }
+        write_option(writer, self.custom_type_info.as_ref(), |writer, info| {
+            write_string(writer, &info.struct_name)?;
+            writer.write_all(&info.size.to_le_bytes())
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyField {
+    pub field_type: u16,
+    pub num: u32,
+    pub data: Option<Vec<u8>>,
+}
+
+impl PropertyField {
+    // Type ids of the fields that carry a default value
+    const VEC4: u16 = 2;
+    const MAT3: u16 = 3;
+    const MAT4: u16 = 4;
+
+    /// Size of the default value for this field type
+    fn data_len(field_type: u16) -> Option<usize> {
+        let floats = match field_type {
+            Self::VEC4 => 4,
+            Self::MAT3 => 9,
+            Self::MAT4 => 16,
+            _ => return None,
+        };
+        Some(floats * std::mem::size_of::<f32>())
+    }
+    pub(super) fn read(src: &[u8], offset: &mut usize) -> Result<Self, scroll::Error> {
+        let field_type = src.gread_with(offset, LE)?;
+        let num = src.gread_with(offset, LE)?;
+        let data = read_option(src, offset, |src, offset| {
+            let len =
+                Self::data_len(field_type).ok_or(bad_input("Property type cannot have data"))?;
+            let end = *offset + len;
+            let data = src.get(*offset..end).ok_or(scroll::Error::TooBig {
+                size: len,
+                len: src.len(),
+            })?;
+            *offset = end;
+            Ok(data.to_vec())
+        })?;
+        Ok(Self {
+            field_type,
+            num,
+            data,
+        })
+    }
+    pub(super) fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.field_type.to_le_bytes())?;
+        writer.write_all(&self.num.to_le_bytes())?;
+        write_option(writer, self.data.as_deref(), |writer, data| {
+            writer.write_all(data)
+        })
+    }
+}
@@ -0,0 +1,119 @@
+// Byte patterns with wildcards, written like `FF ?? 02 D1`
+use memchr::memmem;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BytePattern {
+    bytes: Vec<Option<u8>>,
+}
+
+impl BytePattern {
+    /// Parse space separated hex bytes, `?` and `??` are wildcards
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bytes = text
+            .split_whitespace()
+            .map(|token| match token {
+                "?" | "??" => Ok(None),
+                hex if hex.len() == 2 => u8::from_str_radix(hex, 16)
+                    .map(Some)
+                    .map_err(|_| format!("Invalid pattern byte {hex}")),
+                other => Err(format!("Invalid pattern byte {other}")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if bytes.iter().all(Option::is_none) {
+            return Err("Pattern has no fixed bytes".to_owned());
+        }
+        Ok(Self { bytes })
+    }
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+    /// How many bytes are not wildcards, more means less chance of matching the wrong code
+    pub fn specificity(&self) -> usize {
+        self.bytes.iter().flatten().count()
+    }
+    /// Check the pattern against the bytes at `offset`
+    pub fn matches_at(&self, haystack: &[u8], offset: usize) -> bool {
+        let Some(window) = haystack.get(offset..offset.saturating_add(self.len())) else {
+            return false;
+        };
+        window.len() == self.len()
+            && self
+                .bytes
+                .iter()
+                .zip(window)
+                .all(|(expected, byte)| expected.is_none_or(|expected| expected == *byte))
+    }
+    // The longest run without wildcards, searched for first since memmem is much faster than us
+    fn anchor(&self) -> (usize, Vec<u8>) {
+        let mut best = (0, Vec::new());
+        let mut start = 0;
+        for (index, byte) in self.bytes.iter().chain([&None]).enumerate() {
+            if byte.is_some() {
+                continue;
+            }
+            let run: Vec<u8> = self
+                .bytes
+                .get(start..index)
+                .unwrap_or_default()
+                .iter()
+                .flatten()
+                .copied()
+                .collect();
+            if run.len() > best.1.len() {
+                best = (start, run);
+            }
+            start = index + 1;
+        }
+        best
+    }
+    fn matches<'a>(
+        &'a self,
+        haystack: &'a [u8],
+        anchor: &'a (usize, Vec<u8>),
+    ) -> impl Iterator<Item = usize> + 'a {
+        let (anchor_offset, anchor_bytes) = anchor;
+        memmem::find_iter(haystack, anchor_bytes)
+            .filter_map(move |found| found.checked_sub(*anchor_offset))
+            .filter(move |start| self.matches_at(haystack, *start))
+    }
+    /// Offsets of every match, in order
+    pub fn find_all(&self, haystack: &[u8]) -> Vec<usize> {
+        self.matches(haystack, &self.anchor()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_anything() {
+        let pattern = BytePattern::parse("FF ?? 02").expect("valid pattern");
+        assert_eq!(
+            pattern.find_all(&[0x00, 0xFF, 0x01, 0x02, 0xFF, 0x03, 0x02]),
+            [1, 4]
+        );
+        assert_eq!(pattern.specificity(), 2);
+    }
+
+    #[test]
+    fn matches_are_found_around_the_anchor() {
+        // The longest fixed run is in the middle, the bytes before it still have to match
+        let pattern = BytePattern::parse("AA ? 01 02 03 ? BB").expect("valid pattern");
+        let haystack = [
+            0x01, 0x02, 0x03, 0xAA, 0x00, 0x01, 0x02, 0x03, 0x00, 0xBB, 0x01, 0x02,
+        ];
+        assert_eq!(pattern.find_all(&haystack), [3]);
+        assert!(!pattern.matches_at(&haystack, 8));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(BytePattern::parse("?? ??").is_err());
+        assert!(BytePattern::parse("FF 0").is_err());
+        assert!(BytePattern::parse("FF GG").is_err());
+    }
+}
@@ -1,99 +1,577 @@
 // #[cfg(target_pointer_width = "64")]
-use std::{borrow::Cow, collections::HashMap};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    ffi::{CStr, CString},
+    sync::Arc,
+};
 
 // #[cfg(target_pointer_width = "64")]
 // use plt_rs::elf64::{self, DynRela};
 use plt_rs::DynamicLibrary;
-use region::{protect, protect_with_handle, Protection};
+use region::{protect, Protection};
 
-pub fn replace_plt_functions<const LEN: usize>(
+/// A GOT entry we replaced, remembers what was there before so it can be called or put back
+#[cfg_attr(test, allow(dead_code))]
+#[derive(Debug)]
+pub struct PltHook {
+    slot: *mut *const u8,
+    original: *const u8,
+    replacement: *const u8,
+}
+// The pointers are just addresses, nothing here is tied to a thread
+unsafe impl Send for PltHook {}
+unsafe impl Sync for PltHook {}
+
+#[cfg_attr(test, allow(dead_code))]
+impl PltHook {
+    /// The function the entry pointed to before us, might be another mod's hook
+    ///
+    /// # Safety
+    /// `F` has to be the function pointer type of the hooked function
+    pub unsafe fn original<F: Copy>(&self) -> F {
+        assert_eq!(std::mem::size_of::<F>(), std::mem::size_of::<*const u8>());
+        std::mem::transmute_copy(&self.original)
+    }
+    /// Put the original entry back.
+    /// If another mod hooked the entry after us it is left alone, as that would remove their hook too
+    pub fn uninstall(self) -> Result<(), Arc<region::Error>> {
+        unsafe {
+            if self.slot.read_unaligned() != self.replacement {
+                log::warn!("GOT entry was hooked again after us, leaving it alone");
+                return Ok(());
+            }
+            write_slots(&[(self.slot, self.original)])
+                .pop()
+                .unwrap_or(Ok(()))
+        }
+    }
+}
+
+/// Find an exported function of an already loaded library, to inline hook it
+#[cfg_attr(test, allow(dead_code))]
+pub fn find_export(library: &CStr, symbol: &str) -> Option<*mut u8> {
+    let symbol = CString::new(symbol).ok()?;
+    unsafe {
+        // Only look at what is loaded already, never load anything ourselves
+        let handle = libc::dlopen(library.as_ptr(), libc::RTLD_NOW | libc::RTLD_NOLOAD);
+        if handle.is_null() {
+            return None;
+        }
+        let addr = libc::dlsym(handle, symbol.as_ptr());
+        // The handle only holds an extra reference, the library stays loaded
+        libc::dlclose(handle);
+        (!addr.is_null()).then_some(addr.cast())
+    }
+}
+
+/// A library to hook, picked by a part of its name or by its full path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookTarget(String);
+
+impl HookTarget {
+    pub fn new(target: &str) -> Self {
+        Self(target.to_owned())
+    }
+    /// Check a loaded library path against this target
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn matches(&self, library: &str) -> bool {
+        match self.0.contains('/') {
+            true => library == self.0,
+            false => library.contains(&self.0),
+        }
+    }
+    /// Only the game itself by default
+    pub fn defaults() -> Vec<Self> {
+        vec![Self::new("libminecraftpe")]
+    }
+    /// Load the targets from the launcher files dir, the defaults are used if there is none
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn load_list() -> Vec<Self> {
+        match crate::read_config_list("hook_targets.txt") {
+            Some(targets) => targets.iter().map(|target| Self::new(target)).collect(),
+            None => Self::defaults(),
+        }
+    }
+}
+
+/// What happened to a single function we tried to hook
+#[cfg_attr(test, allow(dead_code))]
+#[derive(Debug)]
+pub enum PatchStatus {
+    Patched(PltHook),
+    /// The library does not import this function through its PLT/GOT
+    Missing,
+    /// The entry already pointed to our replacement
+    AlreadyPatched,
+    /// The page could not be made writable, shared by every entry on that page
+    ProtectFailed(Arc<region::Error>),
+    /// Not attempted because a required function is missing
+    Skipped,
+}
+
+/// Per symbol results of [`replace_plt_functions`]
+#[cfg_attr(test, allow(dead_code))]
+#[derive(Debug, Default)]
+pub struct PatchReport<'a> {
+    pub entries: Vec<(&'a str, PatchStatus)>,
+}
+
+#[cfg_attr(test, allow(dead_code))]
+impl<'a> PatchReport<'a> {
+    pub fn status(&self, name: &str) -> Option<&PatchStatus> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| *entry == name)
+            .map(|(_, status)| status)
+    }
+    /// Check if calls to a function now go through our replacement
+    pub fn is_hooked(&self, name: &str) -> bool {
+        matches!(
+            self.status(name),
+            Some(PatchStatus::Patched(_) | PatchStatus::AlreadyPatched)
+        )
+    }
+    /// Take out the hooks that were installed, dropping them keeps the hooks in place
+    pub fn into_hooks(self) -> impl Iterator<Item = (&'a str, PltHook)> {
+        self.entries
+            .into_iter()
+            .filter_map(|(name, status)| match status {
+                PatchStatus::Patched(hook) => Some((name, hook)),
+                _ => None,
+            })
+    }
+    pub fn log(&self, library: &str) {
+        for (name, status) in &self.entries {
+            match status {
+                PatchStatus::Patched(_) => log::info!("Hooked {name} in {library}"),
+                PatchStatus::AlreadyPatched => log::info!("{name} was already hooked in {library}"),
+                PatchStatus::Missing => log::warn!("{name} is not imported by {library}"),
+                PatchStatus::ProtectFailed(e) => {
+                    log::error!("Cannot hook {name} in {library}: {e}")
+                }
+                PatchStatus::Skipped => log::warn!("Skipped hooking {name} in {library}"),
+            }
+        }
+    }
+}
+
+/// Hook `functions` in the library relocation tables.
+/// Nothing gets patched if any function in `required` is missing
+#[cfg_attr(test, allow(dead_code))]
+pub fn replace_plt_functions<'a>(
     dyn_lib: &DynamicLibrary,
-    functions: [(&str, *const u8); LEN],
-) {
+    functions: impl IntoIterator<Item = (&'a str, *const u8)>,
+    required: &[&str],
+) -> PatchReport<'a> {
     let base_addr = dyn_lib.library().addr();
-    let Some(table) = get_function_table(dyn_lib) else {
-        log::warn!("Wtf");
-        return;
-    };
+    let table = get_function_table(dyn_lib);
+    if table.is_none() {
+        log::warn!(
+            "No relocations found to hook in {}",
+            dyn_lib.library().name()
+        );
+    }
+    let table = table.unwrap_or_default();
+    let complete = required.iter().all(|name| table.contains_key(*name));
+    let mut entries = Vec::new();
+    let mut pending = Vec::new();
     for (fn_name, replacement) in functions {
-        let name = Cow::Borrowed(fn_name);
-        if let Some(fn_plt) = table.get(&name) {
-            replace_plt_function(base_addr, fn_plt.r_offset as usize, replacement);
+        let status = match table.get(fn_name) {
+            None => PatchStatus::Missing,
+            Some(_) if !complete => PatchStatus::Skipped,
+            Some(fn_plt) => {
+                let slot = (base_addr + fn_plt.offset()) as *mut *const u8;
+                let original = unsafe { slot.read_unaligned() };
+                if original == replacement {
+                    PatchStatus::AlreadyPatched
+                } else {
+                    pending.push((entries.len(), slot, original, replacement));
+                    // Filled in once the page is written
+                    PatchStatus::Skipped
+                }
+            }
+        };
+        entries.push((fn_name, status));
+    }
+    let writes: Vec<_> = pending
+        .iter()
+        .map(|(_, slot, _, replacement)| (*slot, *replacement))
+        .collect();
+    let results = unsafe { write_slots(&writes) };
+    for ((index, slot, original, replacement), result) in pending.into_iter().zip(results) {
+        let status = match result {
+            Ok(()) => PatchStatus::Patched(PltHook {
+                slot,
+                original,
+                replacement,
+            }),
+            Err(e) => PatchStatus::ProtectFailed(e),
+        };
+        if let Some(entry) = entries.get_mut(index) {
+            entry.1 = status;
         }
     }
+    PatchReport { entries }
 }
-fn replace_plt_function(base_addr: usize, offset: usize, replacement: *const u8) {
-    let plt_fn_ptr = (base_addr + offset) as *mut *const u8;
+
+/// Write pointers into GOT slots, every page is made writable once for all of its slots
+/// and gets its previous protection back afterwards.
+/// Returns one result per write, in the same order
+#[cfg_attr(test, allow(dead_code))]
+unsafe fn write_slots(
+    writes: &[(*mut *const u8, *const u8)],
+) -> Vec<Result<(), Arc<region::Error>>> {
     const PTR_LEN: usize = std::mem::size_of::<usize>();
-    unsafe {
-        // Set the memory page to read, write
-        let _handle =
-            protect(plt_fn_ptr, PTR_LEN, Protection::READ_WRITE).expect("Mprotect failed");
-        // Replace the function address
-        plt_fn_ptr.write_unaligned(replacement);
-        protect(plt_fn_ptr, PTR_LEN, Protection::READ_EXECUTE).unwrap();
+    let mut pages: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (index, (slot, _)) in writes.iter().enumerate() {
+        let page = region::page::floor(*slot) as usize;
+        pages.entry(page).or_default().push(index);
     }
+    let mut results: Vec<_> = writes.iter().map(|_| Ok(())).collect();
+    for (page, indexes) in pages {
+        let page_writes = indexes.iter().filter_map(|index| writes.get(*index));
+        // A slot could cross into the next page
+        let end = page_writes
+            .clone()
+            .map(|(slot, _)| region::page::ceil(slot.byte_add(PTR_LEN)) as usize)
+            .max()
+            .unwrap_or(page);
+        let result = with_writable(page as *const u8, end - page, || {
+            for (slot, value) in page_writes {
+                slot.write_unaligned(*value);
+            }
+        });
+        if let Err(e) = result {
+            let e = Arc::new(e);
+            for index in indexes {
+                if let Some(result) = results.get_mut(index) {
+                    *result = Err(e.clone());
+                }
+            }
+        }
+    }
+    results
 }
 
-// /// Finding target function differs on 32 bit and 64 bit.
-// /// On 32 bit we want to check the relocations table only, opposed to the addend relocations table.
-// /// Additionally, we will fall back to the plt given it is an addendless relocation table.
+/// Run `write` with the pages of the range writable, then restore the protection each of them had before
+unsafe fn with_writable(
+    start: *const u8,
+    len: usize,
+    write: impl FnOnce(),
+) -> Result<(), region::Error> {
+    let page_size = region::page::size();
+    let first = region::page::floor(start) as usize;
+    // GOT pages are usually read only after relocation (RELRO), but some are left writable
+    let mut changed = Vec::new();
+    for page in (first..start as usize + len).step_by(page_size) {
+        let page = page as *const u8;
+        let result = region::query(page).and_then(|region| {
+            let old = region.protection();
+            if !old.contains(Protection::WRITE) {
+                protect(page, page_size, old | Protection::WRITE)?;
+                changed.push((page, old));
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            restore_protection(&changed);
+            return Err(e);
+        }
+    }
+    write();
+    restore_protection(&changed);
+    Ok(())
+}
 
-macro_rules! collect_entries {
-    ($iter:ident,$syms:expr,$table:expr) => {
-        $iter
-            .entries()
-            .iter()
-            .map(|e| {
-                $syms
-                    .resolve_name(e.symbol_index() as usize, $table)
-                    .map(|s| (s, e))
-            })
-            .flatten()
-        //            .collect()
-    };
+/// Put back the protection of pages [`with_writable`] changed
+unsafe fn restore_protection(pages: &[(*const u8, Protection)]) {
+    let page_size = region::page::size();
+    for (page, old) in pages {
+        // The entries are already written, a page left writable is not worth failing over
+        if let Err(e) = protect(*page, page_size, *old) {
+            log::error!("Failed to restore protection of {page:?}: {e}");
+        }
+    }
+}
+
+/// A relocation table entry, REL on 32 bit and RELA on 64 bit
+pub trait Relocation {
+    fn symbol_index(&self) -> usize;
+    /// Where the entry is, relative to the library base
+    fn offset(&self) -> usize;
 }
 
 #[cfg(target_pointer_width = "32")]
-pub fn get_function_table<'a>(
-    dnlib: &'a DynamicLibrary,
-) -> Option<HashMap<Cow<'a, str>, &'a plt_rs::elf32::DynRel>> {
-    let string_table = dnlib.string_table();
-    let dyn_symbols = dnlib.symbols()?;
-    let mut hashmap = HashMap::new();
-    if let Some(dyn_relas) = dnlib.relocs() {
-        let iter = collect_entries!(dyn_relas, dyn_symbols, string_table);
-        hashmap.extend(iter);
+impl Relocation for plt_rs::elf32::DynRel {
+    fn symbol_index(&self) -> usize {
+        self.symbol_index() as usize
+    }
+    fn offset(&self) -> usize {
+        self.r_offset as usize
     }
+}
 
-    if let Some(dyn_relas) = dnlib.plt_rel() {
-        let iter = collect_entries!(dyn_relas, dyn_symbols, string_table);
-        hashmap.extend(iter);
+#[cfg(target_pointer_width = "64")]
+impl Relocation for plt_rs::elf64::DynRela {
+    fn symbol_index(&self) -> usize {
+        self.symbol_index() as usize
+    }
+    fn offset(&self) -> usize {
+        self.r_offset as usize
     }
-    None
 }
 
-// /// Finding target function differs on 32 bit and 64 bit.
-// /// On 64 bit we want to check the addended relocations table only, opposed to the addendless relocations table.
-// /// Additionally, we will fall back to the plt given it is an addended relocation table.
+/// What the function table is read from, a loaded library or an ELF file in tests
+pub trait DynamicTables {
+    type Entry: Relocation;
+    /// The GLOB_DAT and other relocations, .rel.dyn or .rela.dyn
+    fn dyn_relocs(&self) -> Option<&[Self::Entry]>;
+    /// The jump slots, .rel.plt or .rela.plt
+    fn plt_relocs(&self) -> Option<&[Self::Entry]>;
+    fn symbol_name(&self, index: usize) -> Option<Cow<'_, str>>;
+}
+
+/// Finding target function differs on 32 bit and 64 bit.
+/// On 32 bit we want to check the relocations table only, opposed to the addend relocations table.
+/// Additionally, we will fall back to the plt given it is an addendless relocation table.
+#[cfg(target_pointer_width = "32")]
+impl DynamicTables for DynamicLibrary<'_> {
+    type Entry = plt_rs::elf32::DynRel;
+    fn dyn_relocs(&self) -> Option<&[Self::Entry]> {
+        self.relocs().map(|table| table.entries())
+    }
+    fn plt_relocs(&self) -> Option<&[Self::Entry]> {
+        self.plt_rel().map(|table| table.entries())
+    }
+    fn symbol_name(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.symbols()?.resolve_name(index, self.string_table())
+    }
+}
+
+/// Finding target function differs on 32 bit and 64 bit.
+/// On 64 bit we want to check the addended relocations table only, opposed to the addendless relocations table.
+/// Additionally, we will fall back to the plt given it is an addended relocation table.
 #[cfg(target_pointer_width = "64")]
-pub fn get_function_table<'a>(
-    dnlib: &'a DynamicLibrary,
-) -> Option<HashMap<Cow<'a, str>, &'a plt_rs::elf64::DynRela>> {
-    let string_table = dnlib.string_table();
-    let symbols = dnlib.symbols()?;
-    let mut hashmap = HashMap::new();
-    if let Some(dyn_relas) = dnlib.addend_relocs() {
-        let entries = collect_entries!(dyn_relas, &symbols, &string_table);
-        hashmap.extend(entries);
-    }
-    if let Some(dyn_relas) = dnlib.plt_rela() {
-        let entries = collect_entries!(dyn_relas, &symbols, &string_table);
-        hashmap.extend(entries);
-    }
-    if hashmap.is_empty() {
-        None
-    } else {
-        Some(hashmap)
+impl DynamicTables for DynamicLibrary<'_> {
+    type Entry = plt_rs::elf64::DynRela;
+    fn dyn_relocs(&self) -> Option<&[Self::Entry]> {
+        self.addend_relocs().map(|table| table.entries())
+    }
+    fn plt_relocs(&self) -> Option<&[Self::Entry]> {
+        self.plt_rela().map(|table| table.entries())
+    }
+    fn symbol_name(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.symbols()?.resolve_name(index, self.string_table())
+    }
+}
+
+/// Map symbol names to their relocation entries, None if no entry has a name
+pub fn get_function_table<T: DynamicTables>(
+    library: &T,
+) -> Option<HashMap<Cow<'_, str>, &T::Entry>> {
+    // Jump slots go last so they win over GLOB_DAT entries of the same symbol
+    let hashmap: HashMap<_, _> = [library.dyn_relocs(), library.plt_relocs()]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            library
+                .symbol_name(entry.symbol_index())
+                .map(|name| (name, entry))
+        })
+        .collect();
+    (!hashmap.is_empty()).then_some(hashmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scroll::{Pread, LE};
+    use std::{fs, path::PathBuf};
+
+    const SHT_RELA: u32 = 4;
+    const SHT_REL: u32 = 9;
+    // Set on .rel.plt and .rela.plt, which point at the GOT they fill
+    const SHF_INFO_LINK: usize = 0x40;
+
+    /// What the dynamic linker sees of one entry, REL and RELA alike
+    struct FileRelocation {
+        offset: usize,
+        symbol: usize,
+    }
+
+    impl Relocation for FileRelocation {
+        fn symbol_index(&self) -> usize {
+            self.symbol
+        }
+        fn offset(&self) -> usize {
+            self.offset
+        }
+    }
+
+    /// The dynamic relocation tables of a fixture and the names of .dynsym
+    #[derive(Default)]
+    struct ElfTables {
+        dyn_relocs: Option<Vec<FileRelocation>>,
+        plt_relocs: Option<Vec<FileRelocation>>,
+        names: Vec<String>,
+    }
+
+    impl DynamicTables for ElfTables {
+        type Entry = FileRelocation;
+        fn dyn_relocs(&self) -> Option<&[FileRelocation]> {
+            self.dyn_relocs.as_deref()
+        }
+        fn plt_relocs(&self) -> Option<&[FileRelocation]> {
+            self.plt_relocs.as_deref()
+        }
+        fn symbol_name(&self, index: usize) -> Option<Cow<'_, str>> {
+            self.names
+                .get(index)
+                .map(|name| Cow::Borrowed(name.as_str()))
+        }
+    }
+
+    fn read_word(data: &[u8], offset: &mut usize, is_64: bool) -> usize {
+        let word = match is_64 {
+            true => data.gread_with::<u64>(offset, LE).expect("in file"),
+            false => data.gread_with::<u32>(offset, LE).expect("in file").into(),
+        };
+        usize::try_from(word).expect("fits")
+    }
+
+    fn read_tables(name: &str) -> ElfTables {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/plthook")
+            .join(name);
+        let data = fs::read(path).expect("ELF fixture");
+        let is_64 = data.get(4) == Some(&2);
+        let word = if is_64 { 8 } else { 4 };
+        let shoff = read_word(&data, &mut (24 + 2 * word), is_64);
+        let shentsize: u16 = data.pread_with(34 + 3 * word, LE).expect("header");
+        let shnum: u16 = data.pread_with(36 + 3 * word, LE).expect("header");
+        // (type, flags, offset, size, link) of every section
+        let sections: Vec<(u32, usize, usize, usize, usize)> = (0..usize::from(shnum))
+            .map(|index| {
+                let offset = &mut (shoff + index * usize::from(shentsize));
+                *offset += 4;
+                let kind: u32 = data.gread_with(offset, LE).expect("section");
+                let flags = read_word(&data, offset, is_64);
+                *offset += word;
+                let file_offset = read_word(&data, offset, is_64);
+                let size = read_word(&data, offset, is_64);
+                let link: u32 = data.gread_with(offset, LE).expect("section");
+                (kind, flags, file_offset, size, link as usize)
+            })
+            .collect();
+        let section = |index: usize| {
+            let (_, _, offset, size, link) = *sections.get(index).expect("linked section");
+            (data.get(offset..offset + size).expect("in file"), link)
+        };
+        let mut tables = ElfTables::default();
+        let mut dynsym = None;
+        for (kind, flags, offset, size, link) in &sections {
+            let entry_len = match (*kind, is_64) {
+                (SHT_REL, false) => 2 * word,
+                (SHT_RELA, true) => 3 * word,
+                _ => continue,
+            };
+            dynsym = Some(*link);
+            let entries = data.get(*offset..offset + size).expect("in file");
+            let table = entries
+                .chunks_exact(entry_len)
+                .map(|entry| {
+                    let offset = &mut 0;
+                    let r_offset = read_word(entry, offset, is_64);
+                    let info = read_word(entry, offset, is_64);
+                    FileRelocation {
+                        offset: r_offset,
+                        symbol: if is_64 { info >> 32 } else { info >> 8 },
+                    }
+                })
+                .collect();
+            match flags & SHF_INFO_LINK != 0 {
+                true => tables.plt_relocs = Some(table),
+                false => tables.dyn_relocs = Some(table),
+            }
+        }
+        let (symbols, strings) = section(dynsym.expect("relocations"));
+        let (strings, _) = section(strings);
+        tables.names = symbols
+            .chunks_exact(if is_64 { 24 } else { 16 })
+            .map(|symbol| {
+                let name: u32 = symbol.pread_with(0, LE).expect("symbol");
+                let name = strings.get(name as usize..).expect("in table");
+                let end = name.iter().position(|c| *c == 0).expect("terminated");
+                String::from_utf8_lossy(name.get(..end).expect("in table")).into_owned()
+            })
+            .collect();
+        tables
+    }
+
+    fn offsets(tables: &ElfTables) -> Option<Vec<(String, usize)>> {
+        let mut offsets: Vec<_> = get_function_table(tables)?
+            .into_iter()
+            .map(|(name, entry)| (name.into_owned(), entry.offset()))
+            .collect();
+        offsets.sort_unstable();
+        Some(offsets)
+    }
+
+    #[test]
+    fn every_page_gets_its_own_protection_back() {
+        let page_size = region::page::size();
+        let memory = region::alloc(2 * page_size, Protection::READ).expect("allocated");
+        let start = memory.as_ptr::<u8>();
+        unsafe {
+            protect(start, page_size, Protection::READ_WRITE).expect("protected");
+            let second = start.add(page_size);
+            with_writable(start, 2 * page_size, || {
+                start.cast_mut().write(1);
+                second.cast_mut().write(2);
+            })
+            .expect("written");
+            assert_eq!((start.read(), second.read()), (1, 2));
+            let protection = |page| region::query(page).expect("mapped").protection();
+            assert_eq!(protection(start), Protection::READ_WRITE);
+            assert_eq!(protection(second), Protection::READ);
+        }
+    }
+
+    #[test]
+    fn rel_tables_are_read() {
+        let tables = read_tables("imports32.elf");
+        // AAsset_read also has a GOT entry at 0x400c, the jump slot is the one we want
+        assert_eq!(
+            offsets(&tables).expect("32 bit libraries have imports too"),
+            [
+                ("AAssetManager_open".to_owned(), 0x4000),
+                ("AAsset_close".to_owned(), 0x4004),
+                ("AAsset_read".to_owned(), 0x4008),
+            ]
+        );
+    }
+
+    #[test]
+    fn rela_tables_are_read() {
+        let tables = read_tables("imports64.elf");
+        assert_eq!(
+            offsets(&tables).expect("imports"),
+            [
+                ("AAssetManager_open".to_owned(), 0x4000),
+                ("AAsset_close".to_owned(), 0x4008),
+                ("AAsset_read".to_owned(), 0x4010),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_named_entries_is_none() {
+        let tables = ElfTables {
+            names: Vec::new(),
+            ..read_tables("imports64.elf")
+        };
+        assert!(get_function_table(&tables).is_none());
     }
 }
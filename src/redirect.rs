@@ -0,0 +1,366 @@
+// Which apk files get replaced, and where in a pack they come from
+use std::{
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
+
+pub static REDIRECTS: LazyLock<Mutex<RedirectTable>> =
+    LazyLock::new(|| Mutex::new(RedirectTable::default()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Everything under a folder, the rest of the path is kept
+    Prefix,
+    /// A single file
+    Exact,
+    /// Paths matching a glob, the part after the first wildcard folder is kept
+    Glob,
+}
+
+#[derive(Debug, Clone)]
+pub struct RedirectRule {
+    pub kind: MatchKind,
+    pub apk: String,
+    pub pack: String,
+}
+
+impl RedirectRule {
+    fn new(kind: MatchKind, apk: &str, pack: &str) -> Self {
+        Self {
+            kind,
+            apk: apk.to_owned(),
+            pack: pack.to_owned(),
+        }
+    }
+    /// Get the pack path for an apk path if this rule covers it
+    fn resolve(&self, path: &[u8]) -> Option<PathBuf> {
+        let rest = match self.kind {
+            MatchKind::Prefix => path.strip_prefix(self.apk.as_bytes())?,
+            MatchKind::Exact => {
+                return (path == self.apk.as_bytes()).then(|| PathBuf::from(&self.pack));
+            }
+            MatchKind::Glob => {
+                if !glob_match(self.apk.as_bytes(), path) {
+                    return None;
+                }
+                path.get(literal_prefix(self.apk.as_bytes()).len()..)?
+            }
+        };
+        let mut pack_path = self.pack.as_bytes().to_vec();
+        pack_path.extend_from_slice(rest);
+        Some(PathBuf::from(std::ffi::OsStr::from_bytes(&pack_path)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    Include,
+    Exclude,
+}
+
+/// Decides if apk paths matching a glob may be redirected at all
+#[derive(Debug, Clone)]
+pub struct FilterRule {
+    pub action: FilterAction,
+    pub pattern: String,
+}
+
+/// Ordered redirection rules, the first matching rule wins.
+/// Filters are checked first in the same way, paths no filter matches are included
+#[derive(Debug, Clone)]
+pub struct RedirectTable {
+    rules: Vec<RedirectRule>,
+    filters: Vec<FilterRule>,
+}
+
+impl Default for RedirectTable {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                RedirectRule::new(MatchKind::Prefix, "gui/dist/hbui/", "hbui/"),
+                RedirectRule::new(MatchKind::Prefix, "skin_packs/persona/", "persona/"),
+                RedirectRule::new(MatchKind::Prefix, "renderer/", "renderer/"),
+                RedirectRule::new(
+                    MatchKind::Prefix,
+                    "resource_packs/vanilla/cameras/",
+                    "vanilla_cameras/",
+                ),
+            ],
+            filters: Vec::new(),
+        }
+    }
+}
+
+impl RedirectTable {
+    /// Parse a table made of `<prefix|exact|glob> <apk path> <pack path>`
+    /// and `<include|exclude> <apk glob>` lines,
+    /// empty lines and lines starting with # are ignored
+    pub fn parse(text: &str) -> Self {
+        let mut rules = Vec::new();
+        let mut filters = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_line(line) {
+                Some(Line::Redirect(rule)) => rules.push(rule),
+                Some(Line::Filter(filter)) => filters.push(filter),
+                None => log::warn!("Ignoring invalid redirect on line {}: {line}", number + 1),
+            }
+        }
+        Self { rules, filters }
+    }
+    /// Load the table from the launcher files dir, the defaults are used if there is none
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn load() -> Self {
+        let Some(path) = crate::files_dir().map(|dir| dir.join("mbl2/redirects.txt")) else {
+            return Self::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                log::info!("Loading redirects from {}", path.display());
+                Self::parse(&text)
+            }
+            Err(_) => Self::default(),
+        }
+    }
+    /// Get where in the packs an apk file (without assets/) should come from
+    pub fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        let path = path.as_os_str().as_bytes();
+        if !self.is_allowed(path) {
+            return None;
+        }
+        self.rules.iter().find_map(|rule| rule.resolve(path))
+    }
+    /// Check the filters for an apk path
+    pub fn is_allowed(&self, path: &[u8]) -> bool {
+        self.filters
+            .iter()
+            .find(|filter| glob_match(filter.pattern.as_bytes(), path))
+            .is_none_or(|filter| filter.action == FilterAction::Include)
+    }
+}
+
+enum Line {
+    Redirect(RedirectRule),
+    Filter(FilterRule),
+}
+
+fn parse_line(line: &str) -> Option<Line> {
+    let mut words = line.split_whitespace();
+    let directive = words.next()?;
+    let line = match directive {
+        "include" | "exclude" => {
+            let action = match directive {
+                "include" => FilterAction::Include,
+                _ => FilterAction::Exclude,
+            };
+            let pattern = words.next()?.to_owned();
+            Line::Filter(FilterRule { action, pattern })
+        }
+        _ => {
+            let kind = match directive {
+                "prefix" => MatchKind::Prefix,
+                "exact" => MatchKind::Exact,
+                "glob" => MatchKind::Glob,
+                _ => return None,
+            };
+            let apk = words.next()?;
+            let pack = words.next()?;
+            Line::Redirect(RedirectRule::new(kind, apk, pack))
+        }
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    Some(line)
+}
+
+/// The part of a glob before the folder containing the first wildcard
+fn literal_prefix(pattern: &[u8]) -> &[u8] {
+    let wildcard = pattern
+        .iter()
+        .position(|c| matches!(c, b'*' | b'?'))
+        .unwrap_or(pattern.len());
+    let folder_end = pattern
+        .get(..wildcard)
+        .and_then(|literal| literal.iter().rposition(|c| *c == b'/'))
+        .map_or(0, |slash| slash + 1);
+    pattern.get(..folder_end).unwrap_or_default()
+}
+
+/// One element of a glob
+#[derive(Debug, Clone, Copy)]
+enum GlobToken {
+    Byte(u8),
+    /// `?`, any byte but `/`
+    AnyByte,
+    /// `*`, anything within a folder
+    Star,
+    /// `**`, anything including folders
+    AnyPath,
+    /// `**/`, any number of whole folders, including none
+    AnyFolders,
+}
+
+fn glob_tokens(pattern: &[u8]) -> Vec<GlobToken> {
+    let mut tokens = Vec::with_capacity(pattern.len());
+    let mut rest = pattern;
+    loop {
+        let (token, tail) = match rest {
+            [] => return tokens,
+            [b'*', b'*', b'/', tail @ ..] => (GlobToken::AnyFolders, tail),
+            [b'*', b'*', tail @ ..] => (GlobToken::AnyPath, tail),
+            [b'*', tail @ ..] => (GlobToken::Star, tail),
+            [b'?', tail @ ..] => (GlobToken::AnyByte, tail),
+            [c, tail @ ..] => (GlobToken::Byte(*c), tail),
+        };
+        tokens.push(token);
+        rest = tail;
+    }
+}
+
+/// Match a path against a glob, `*` and `?` stay in one folder while `**` crosses folders.
+/// Goes through the pattern backwards keeping which path suffixes the rest of it matches,
+/// so nested `**` take time linear in their count instead of exponential
+pub fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    // matches[j]: whether the tokens after the current one match path[j..]
+    let mut matches: Vec<bool> = (0..=path.len()).map(|j| j == path.len()).collect();
+    for token in glob_tokens(pattern).into_iter().rev() {
+        let mut current = Vec::with_capacity(path.len() + 1);
+        // Whether this token and the rest match path[j + 1..]
+        let mut later = false;
+        // Whether path[j..] has a `/` after which the rest matches
+        let mut folders = false;
+        for j in (0..=path.len()).rev() {
+            let byte = path.get(j).copied();
+            let skip = matches.get(j) == Some(&true);
+            let after = matches.get(j + 1) == Some(&true);
+            let matched = match token {
+                GlobToken::Byte(c) => byte == Some(c) && after,
+                GlobToken::AnyByte => byte.is_some_and(|c| c != b'/') && after,
+                GlobToken::Star => skip || (byte.is_some_and(|c| c != b'/') && later),
+                GlobToken::AnyPath => skip || (byte.is_some() && later),
+                GlobToken::AnyFolders => {
+                    folders = (byte == Some(b'/') && after) || folders;
+                    skip || folders
+                }
+            };
+            current.push(matched);
+            later = matched;
+        }
+        current.reverse();
+        matches = current;
+    }
+    matches.first() == Some(&true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_folder_wildcards() {
+        assert!(glob_match(b"fonts/*.ttf", b"fonts/Mojangles.ttf"));
+        assert!(!glob_match(b"fonts/*.ttf", b"fonts/extra/Mojangles.ttf"));
+        assert!(glob_match(b"texts/??_??.lang", b"texts/en_US.lang"));
+        assert!(!glob_match(b"texts/??_??.lang", b"texts/en/US.lang"));
+        assert!(glob_match(b"*", b""));
+        assert!(!glob_match(b"a", b""));
+    }
+
+    #[test]
+    fn double_star_crosses_folders() {
+        assert!(glob_match(
+            b"renderer/**",
+            b"renderer/materials/Sky.material.bin"
+        ));
+        assert!(glob_match(b"renderer/**", b"renderer/"));
+        assert!(!glob_match(b"renderer/**", b"renderer"));
+        assert!(glob_match(b"**.json", b"a/b/c.json"));
+        assert!(glob_match(b"renderer/**/*.bin", b"renderer/a/b/Sky.bin"));
+    }
+
+    #[test]
+    fn double_star_slash_can_match_no_folders() {
+        assert!(glob_match(b"renderer/**/Sky.bin", b"renderer/Sky.bin"));
+        assert!(glob_match(b"**/Sky.bin", b"Sky.bin"));
+        assert!(glob_match(b"**/Sky.bin", b"a/b/Sky.bin"));
+        // Only whole folders
+        assert!(!glob_match(b"**/Sky.bin", b"aSky.bin"));
+        assert!(!glob_match(b"renderer/**/Sky.bin", b"renderer/aSky.bin"));
+    }
+
+    #[test]
+    fn nested_double_stars_finish() {
+        let path = "a/".repeat(30);
+        assert!(!glob_match(b"**/**/**/**/**/x", path.as_bytes()));
+        assert!(glob_match(
+            b"**/**/**/**/**/x",
+            format!("{path}x").as_bytes()
+        ));
+        let path = "a".repeat(200);
+        assert!(!glob_match(b"*a*a*a*a*a*a*a*a*b", path.as_bytes()));
+    }
+
+    #[test]
+    fn glob_rules_keep_what_follows_the_literal_folders() {
+        let rule = RedirectRule::new(MatchKind::Glob, "fonts/*.ttf", "fonts/");
+        assert_eq!(
+            rule.resolve(b"fonts/Mojangles.ttf"),
+            Some(PathBuf::from("fonts/Mojangles.ttf"))
+        );
+        assert_eq!(rule.resolve(b"fonts/Mojangles.otf"), None);
+    }
+
+    #[test]
+    fn first_matching_filter_decides() {
+        let table = RedirectTable::parse(
+            "exclude renderer/materials/RenderChunk.material.bin\n\
+             include renderer/**\n\
+             prefix renderer/ renderer/\n",
+        );
+        assert!(!table.is_allowed(b"renderer/materials/RenderChunk.material.bin"));
+        assert!(table.is_allowed(b"renderer/materials/Sky.material.bin"));
+        assert_eq!(
+            table.resolve(Path::new("renderer/materials/RenderChunk.material.bin")),
+            None
+        );
+        assert_eq!(
+            table.resolve(Path::new("renderer/materials/Sky.material.bin")),
+            Some(PathBuf::from("renderer/materials/Sky.material.bin"))
+        );
+    }
+
+    #[test]
+    fn earlier_include_wins_over_later_exclude() {
+        let table = RedirectTable::parse(
+            "include renderer/**\n\
+             exclude renderer/materials/RenderChunk.material.bin\n",
+        );
+        assert!(table.is_allowed(b"renderer/materials/RenderChunk.material.bin"));
+    }
+
+    #[test]
+    fn unfiltered_paths_are_allowed() {
+        let table = RedirectTable::parse("exclude renderer/**\nprefix texts/ texts/\n");
+        assert!(!table.is_allowed(b"renderer/materials/Sky.material.bin"));
+        assert!(table.is_allowed(b"texts/en_US.lang"));
+        assert!(RedirectTable::default().is_allowed(b"anything/at/all"));
+    }
+
+    #[test]
+    fn invalid_lines_are_skipped() {
+        let table = RedirectTable::parse(
+            "# comment\n\
+             exclude\n\
+             exclude a b\n\
+             rename x y\n\
+             exact texts/en_US.lang texts/en_US.lang\n",
+        );
+        assert!(table.filters.is_empty());
+        assert_eq!(table.rules.len(), 1);
+    }
+}
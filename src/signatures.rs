@@ -0,0 +1,527 @@
+// Signatures of the functions we hook, read from a text file so new game builds don't need a new loader
+use crate::{
+    decode::{self, Arch},
+    pattern::BytePattern,
+    version::{GameVersion, VersionRange},
+};
+use std::fmt;
+
+/// Patterns for the ResourcePackManager constructor of every architecture
+pub const RPMC_PATTERNS: &str = include_str!("signatures.txt");
+/// Every ResourcePackManager constructor variant (C1, C2) starts with this
+pub const RPMC_SYMBOL: &str = "_ZN19ResourcePackManagerC";
+// The first line that is not a comment has to be this plus the format version
+pub const HEADER: &str = "mbl2-signatures";
+pub const FORMAT_VERSION: u32 = 1;
+
+/// How to get from a match to the address we want
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Add this to the match address
+    Offset(isize),
+    /// Follow the call at this offset into the match
+    Call(usize),
+    /// Decode the address loaded at this offset into the match
+    Reference(usize),
+}
+
+impl Target {
+    /// Parse `<offset>`, `call@<offset>` or `ref@<offset>`
+    fn parse(text: &str) -> Option<Self> {
+        if let Some(offset) = text.strip_prefix("call@") {
+            return offset.parse().ok().map(Self::Call);
+        }
+        if let Some(offset) = text.strip_prefix("ref@") {
+            return offset.parse().ok().map(Self::Reference);
+        }
+        text.parse().ok().map(Self::Offset)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub arch: Arch,
+    pub versions: VersionRange,
+    pub target: Target,
+    pub pattern: BytePattern,
+    /// Where the entry came from, for logs
+    pub line: usize,
+}
+
+impl Signature {
+    /// Turn a match at `found` in `code`, which starts at address `base`, into the address we want
+    pub fn resolve(&self, code: &[u8], base: usize, found: usize) -> Option<usize> {
+        let found_addr = base.checked_add(found)?;
+        match self.target {
+            Target::Offset(offset) => found_addr.checked_add_signed(offset),
+            Target::Call(offset) => {
+                let insn = found.checked_add(offset)?;
+                decode::branch_target(self.arch, code.get(insn..)?, base.checked_add(insn)?)
+            }
+            Target::Reference(offset) => {
+                let insn = found.checked_add(offset)?;
+                decode::reference_target(self.arch, code.get(insn..)?, base.checked_add(insn)?)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SignatureDb {
+    pub entries: Vec<Signature>,
+}
+
+impl SignatureDb {
+    /// Parse a signature file, bad entries are skipped and returned as errors.
+    /// A missing or unknown header makes the whole file invalid
+    pub fn parse(text: &str) -> (Self, Vec<ParseError>) {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some((_, header)) if header == format!("{HEADER} {FORMAT_VERSION}") => {}
+            Some((line, header)) => {
+                let message = format!("Expected `{HEADER} {FORMAT_VERSION}`, found `{header}`");
+                return (Self::default(), vec![ParseError { line, message }]);
+            }
+            None => return (Self::default(), Vec::new()),
+        }
+        for (line, text) in lines {
+            match parse_entry(text, line) {
+                Ok(entry) => entries.push(entry),
+                Err(message) => errors.push(ParseError { line, message }),
+            }
+        }
+        (Self { entries }, errors)
+    }
+    /// The compiled in signatures
+    pub fn builtin() -> Self {
+        let (db, errors) = Self::parse(RPMC_PATTERNS);
+        for error in errors {
+            log::error!("Built in signature {error}");
+        }
+        db
+    }
+    pub fn has_arch(&self, arch: Arch) -> bool {
+        self.entries.iter().any(|entry| entry.arch == arch)
+    }
+    /// Signatures for an architecture, limited to the ones made for `version` if any of them are.
+    /// Entries for other versions are still returned when none match, they might just be outdated
+    pub fn select(&self, arch: Arch, version: Option<GameVersion>) -> Vec<&Signature> {
+        let for_arch: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.arch == arch)
+            .collect();
+        let Some(version) = version else {
+            return for_arch;
+        };
+        let for_version: Vec<_> = for_arch
+            .iter()
+            .copied()
+            .filter(|entry| entry.versions.contains(version))
+            .collect();
+        match for_version.is_empty() {
+            true => for_arch,
+            false => for_version,
+        }
+    }
+}
+
+fn parse_entry(text: &str, line: usize) -> Result<Signature, String> {
+    let mut rest = text;
+    let (Some(arch), Some(versions), Some(target)) = (
+        next_word(&mut rest),
+        next_word(&mut rest),
+        next_word(&mut rest),
+    ) else {
+        return Err("Expected <arch> <versions> <target> <pattern>".to_owned());
+    };
+    let pattern = rest;
+    let arch = arch
+        .parse()
+        .map_err(|_| format!("Unknown architecture {arch}"))?;
+    let versions = VersionRange::parse(versions).ok_or(format!("Invalid versions {versions}"))?;
+    let target = Target::parse(target).ok_or(format!("Invalid target {target}"))?;
+    if matches!(
+        (arch, target),
+        (Arch::Arm | Arch::X86, Target::Reference(_))
+    ) {
+        return Err(format!("Address references can't be decoded on {arch}"));
+    }
+    let pattern = BytePattern::parse(pattern)?;
+    Ok(Signature {
+        arch,
+        versions,
+        target,
+        pattern,
+        line,
+    })
+}
+
+/// Split the first word off `text`
+fn next_word<'a>(text: &mut &'a str) -> Option<&'a str> {
+    let trimmed = text.trim_start();
+    let (word, rest) = trimmed
+        .split_once(char::is_whitespace)
+        .unwrap_or((trimmed, ""));
+    *text = rest;
+    (!word.is_empty()).then_some(word)
+}
+
+/// A place a signature matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Start address of the match
+    pub address: usize,
+    /// Where the target of the signature resolved to, None if it could not be decoded
+    pub target: Option<usize>,
+    /// Why the target can't be the function we want, set by [`ScanReport::validate`]
+    pub rejected: Option<String>,
+}
+
+/// Every match of one signature
+#[derive(Debug, Clone)]
+pub struct ScanResult<'a> {
+    pub signature: &'a Signature,
+    pub matches: Vec<Match>,
+}
+
+impl ScanResult<'_> {
+    /// The distinct resolved targets, several call sites of the same function are not ambiguous
+    pub fn targets(&self) -> Vec<usize> {
+        let mut targets: Vec<usize> = self
+            .matches
+            .iter()
+            .filter(|m| m.rejected.is_none())
+            .filter_map(|m| m.target)
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+    }
+    /// The target if the signature points at exactly one place
+    pub fn unique_target(&self) -> Option<usize> {
+        match self.targets().as_slice() {
+            [target] => Some(*target),
+            _ => None,
+        }
+    }
+}
+
+/// Results of every signature over some code
+#[derive(Debug, Clone, Default)]
+pub struct ScanReport<'a> {
+    pub results: Vec<ScanResult<'a>>,
+}
+
+impl<'a> ScanReport<'a> {
+    /// Reject every resolved target `check` fails on, they are left out of [`Self::best`]
+    pub fn validate(&mut self, mut check: impl FnMut(&Signature, usize) -> Result<(), String>) {
+        for result in &mut self.results {
+            for found in &mut result.matches {
+                if let Some(target) = found.target {
+                    found.rejected = check(result.signature, target).err();
+                }
+            }
+        }
+    }
+    /// The unambiguous result of the most specific signature, earlier entries win ties
+    pub fn best(&self) -> Option<(&ScanResult<'a>, usize)> {
+        self.results
+            .iter()
+            .filter_map(|result| result.unique_target().map(|target| (result, target)))
+            .rev()
+            .max_by_key(|(result, _)| result.signature.pattern.specificity())
+    }
+    pub fn log(&self) {
+        for result in &self.results {
+            let line = result.signature.line;
+            let specificity = result.signature.pattern.specificity();
+            match result.targets().as_slice() {
+                [] if result.matches.is_empty() => log::info!("Signature from line {line} did not match"),
+                [] if result.matches.iter().any(|m| m.rejected.is_some()) => {
+                    for found in &result.matches {
+                        if let (Some(target), Some(reason)) = (found.target, &found.rejected) {
+                            log::warn!("Signature from line {line} points to {target:x}, rejected: {reason}");
+                        }
+                    }
+                }
+                [] => log::warn!("Signature from line {line} matched but its target could not be resolved"),
+                [target] => log::info!(
+                    "Signature from line {line} ({specificity} fixed bytes) points to {target:x} from {} matches",
+                    result.matches.len()
+                ),
+                targets => log::warn!(
+                    "Signature from line {line} is ambiguous, it points to {} places: {targets:x?}",
+                    targets.len()
+                ),
+            }
+        }
+    }
+}
+
+/// Run every signature over regions of code, each given with its start address
+pub fn scan<'a>(signatures: &[&'a Signature], regions: &[(usize, &[u8])]) -> ScanReport<'a> {
+    let results = signatures
+        .iter()
+        .map(|signature| {
+            let matches = regions
+                .iter()
+                .flat_map(|(base, code)| {
+                    signature
+                        .pattern
+                        .find_all(code)
+                        .into_iter()
+                        .map(move |found| Match {
+                            address: base + found,
+                            target: signature.resolve(code, *base, found),
+                            rejected: None,
+                        })
+                })
+                .collect();
+            ScanResult { signature, matches }
+        })
+        .collect();
+    ScanReport { results }
+}
+
+/// Find the function the signatures of `arch` point to in `regions`, leaving out targets `check` rejects.
+/// When none of the entries for `version` work every entry for the arch is tried,
+/// one made for another version might still fit a build nobody made signatures for yet
+pub fn find_function(
+    db: &SignatureDb,
+    arch: Arch,
+    version: Option<GameVersion>,
+    regions: &[(usize, &[u8])],
+    mut check: impl FnMut(usize) -> Result<(), String>,
+) -> Option<usize> {
+    let candidates = db.select(arch, version);
+    let every = db.select(arch, None);
+    let fallback = (every.len() != candidates.len()).then_some(every);
+    for (tries, signatures) in std::iter::once(candidates).chain(fallback).enumerate() {
+        if tries > 0 {
+            log::warn!("No signature for this version worked, trying the ones for other versions");
+        }
+        let mut report = scan(&signatures, regions);
+        report.validate(|_, target| check(target));
+        report.log();
+        if let Some((result, target)) = report.best() {
+            log::info!(
+                "Using signature from line {} at {:x}",
+                result.signature.line,
+                target
+            );
+            return Some(target);
+        }
+        log::error!("No signature points to exactly one place");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::ElfInfo;
+    use std::{fs, path::Path};
+
+    fn parse(entries: &str) -> (SignatureDb, Vec<ParseError>) {
+        SignatureDb::parse(&format!("# comment\n{HEADER} {FORMAT_VERSION}\n{entries}"))
+    }
+
+    #[test]
+    fn builtin_signatures_parse() {
+        let (db, errors) = SignatureDb::parse(RPMC_PATTERNS);
+        assert_eq!(errors, []);
+        for arch in [Arch::Aarch64, Arch::Arm, Arch::X86_64] {
+            assert!(db.has_arch(arch), "{arch}");
+        }
+    }
+
+    #[test]
+    fn builtin_ranges_cover_the_builds_they_came_from() {
+        let (db, _) = SignatureDb::parse(RPMC_PATTERNS);
+        let entries = |arch, version: &str| {
+            let version = version.parse().expect("version");
+            db.select(arch, Some(version)).len()
+        };
+        for version in [
+            "1.19.50",
+            "1.21.50.7",
+            "1.21.60.21",
+            "1.21.119",
+            "1.21.120.4",
+            "1.21.130",
+        ] {
+            assert_eq!(entries(Arch::Aarch64, version), 1, "aarch64 {version}");
+        }
+        for version in ["1.19.50", "1.21.110.22", "1.21.120.4", "1.21.130"] {
+            assert_eq!(entries(Arch::Arm, version), 1, "arm {version}");
+        }
+    }
+
+    #[test]
+    fn bad_entries_are_skipped() {
+        let (db, errors) = parse(
+            "aarch64 * 0 FF ?? 02 D1\n\
+             mips * 0 00\n\
+             arm - 0 F0 B5\n\
+             arm * ref@4 F0 B5\n\
+             x86_64 * call@ 55\n\
+             aarch64 1.21.120-\n",
+        );
+        assert_eq!(db.entries.len(), 1);
+        let lines: Vec<_> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn unknown_headers_invalidate_the_file() {
+        let (db, errors) = SignatureDb::parse("mbl2-signatures 2\naarch64 * 0 FF\n");
+        assert!(db.entries.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn targets_parse() {
+        assert_eq!(Target::parse("-4"), Some(Target::Offset(-4)));
+        assert_eq!(Target::parse("call@8"), Some(Target::Call(8)));
+        assert_eq!(Target::parse("ref@12"), Some(Target::Reference(12)));
+        assert_eq!(Target::parse("jump@8"), None);
+    }
+
+    #[test]
+    fn versions_narrow_the_selection_when_they_can() {
+        let (db, _) = parse(
+            "aarch64 1.21.120- 0 01\n\
+             aarch64 -1.21.110 0 02\n\
+             aarch64 * 0 03\n\
+             arm * 0 04\n",
+        );
+        let lines = |version: Option<&str>| {
+            let version = version.map(|version| version.parse().expect("valid version"));
+            db.select(Arch::Aarch64, version)
+                .iter()
+                .map(|entry| entry.line)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lines(Some("1.21.130")), [3, 5]);
+        assert_eq!(lines(Some("1.20.80")), [4, 5]);
+        assert_eq!(lines(None), [3, 4, 5]);
+        let (db, _) = parse("aarch64 1.21.120- 0 01\n");
+        let version = "1.20.80".parse().ok();
+        assert_eq!(db.select(Arch::Aarch64, version).len(), 1);
+    }
+
+    #[test]
+    fn most_specific_unambiguous_signature_wins() {
+        let (db, _) = parse(
+            "x86_64 * 0 55 ??\n\
+             x86_64 * 0 55 41 57\n\
+             x86_64 * 1 55 41\n\
+             x86_64 * 0 90 90\n",
+        );
+        let code = [0x55, 0x41, 0x57, 0xC3, 0x55, 0x41, 0x56];
+        let signatures = db.select(Arch::X86_64, None);
+        let report = scan(&signatures, &[(0x1000, &code)]);
+        let (best, target) = report.best().expect("a unique target");
+        assert_eq!((best.signature.line, target), (4, 0x1000));
+        // Two places, so not usable on its own
+        assert_eq!(
+            report.results.first().and_then(ScanResult::unique_target),
+            None
+        );
+        assert_eq!(
+            report.results.get(3).map(|result| result.matches.len()),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn rejected_targets_are_left_out() {
+        let (db, _) = parse("x86_64 * 0 55 41 57\nx86_64 * 0 55 41\n");
+        let code = [0x55, 0x41, 0x57, 0xC3];
+        let signatures = db.select(Arch::X86_64, None);
+        let mut report = scan(&signatures, &[(0x1000, &code)]);
+        report.validate(|signature, _| match signature.line {
+            3 => Err("not a constructor".to_owned()),
+            _ => Ok(()),
+        });
+        let (best, _) = report.best().expect("a unique target");
+        assert_eq!(best.signature.line, 4);
+    }
+
+    /// Find the constructor in a .text dump the way the loader does, the dump has no symbols
+    /// so only the range and alignment of targets are checked
+    fn dump_target(
+        db: &SignatureDb,
+        arch: Arch,
+        code: &[u8],
+        base: usize,
+        version: Option<GameVersion>,
+    ) -> Option<usize> {
+        let mut elf = ElfInfo::default();
+        elf.text = Some(base as u64..(base + code.len()) as u64);
+        let alignment = arch.function_alignment() as u64;
+        find_function(db, arch, version, &[(base, code)], |target| {
+            elf.check_function(target as u64, alignment, RPMC_SYMBOL)
+        })
+    }
+
+    /// Every arch with built in signatures has .text dumps in tests/fixtures/<arch>, listed in an
+    /// `expected.txt` there as `<file> <.text address> <constructor address> [game version]`
+    #[test]
+    fn builtin_signatures_find_fixture_constructors() {
+        let (db, _) = SignatureDb::parse(RPMC_PATTERNS);
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let hex = |text: &str| {
+            usize::from_str_radix(text.trim_start_matches("0x"), 16).expect("hex address")
+        };
+        for arch in [Arch::Aarch64, Arch::Arm, Arch::X86_64, Arch::X86] {
+            if !db.has_arch(arch) {
+                continue;
+            }
+            let dir = fixtures.join(arch.name());
+            let manifest = fs::read_to_string(dir.join("expected.txt"))
+                .unwrap_or_else(|e| panic!("No expected.txt for {arch}: {e}"));
+            let mut checked = 0;
+            for line in manifest
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                let (name, base, offset, version) = match words.as_slice() {
+                    [name, base, offset] => (name, base, offset, None),
+                    [name, base, offset, version] => (name, base, offset, Some(version)),
+                    _ => panic!("Invalid expected.txt line for {arch}: {line}"),
+                };
+                let code = fs::read(dir.join(name)).expect("dump listed in expected.txt");
+                let version = version.map(|version| version.parse().expect("valid game version"));
+                assert_eq!(
+                    dump_target(&db, arch, &code, hex(base), version),
+                    Some(hex(offset)),
+                    "{arch}/{name}"
+                );
+                checked += 1;
+            }
+            assert!(checked > 0, "No .text dumps listed for {arch}");
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// Places replacement files can come from
+use crate::{
+    cpp_string::ResourceLocation,
+    jniopts::Options,
+    loader::{opt_path_join, Buffer, BufferCursor},
+    redirect::REDIRECTS,
+    zip::ZipArchive,
+    LockResultExt,
+};
+#[cfg(test)]
+use std::collections::HashMap;
+use std::{
+    fs::{self, File},
+    io::{self, Cursor},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Bumped whenever the options that decide the source chain change
+static SOURCES_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Tell file loaders to rebuild their source chain
+pub fn sources_changed() {
+    SOURCES_GENERATION.fetch_add(1, Ordering::Release);
+}
+pub fn sources_generation() -> usize {
+    SOURCES_GENERATION.load(Ordering::Acquire)
+}
+
+/// Something that can provide replacements for apk files
+pub trait AssetSource: Send {
+    /// Name used in logs
+    fn name(&self) -> &str;
+    /// Look up an apk path without the assets/ prefix
+    fn lookup(&mut self, path: &Path) -> Option<Buffer>;
+    /// Whether results can be cached until the sources or packs change
+    fn cacheable(&self) -> bool {
+        true
+    }
+}
+
+/// Where the override directory is checked compared to resource packs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverridePriority {
+    #[default]
+    BeforePacks,
+    AfterPacks,
+}
+
+/// Build the ordered list of sources described by the options
+pub fn build_chain(opts: &Options) -> Vec<Box<dyn AssetSource>> {
+    let mut chain: Vec<Box<dyn AssetSource>> = vec![Box::new(PackSource)];
+    if let Some(path) = &opts.archive {
+        match ZipSource::open(path) {
+            Ok(source) => chain.insert(0, Box::new(source)),
+            Err(e) => log::error!("Cannot open archive {}: {e}", path.display()),
+        }
+    }
+    if let Some(dir) = &opts.override_dir {
+        let source = Box::new(DirSource::new(dir.clone()));
+        match opts.override_priority {
+            OverridePriority::BeforePacks => chain.insert(0, source),
+            OverridePriority::AfterPacks => chain.push(source),
+        }
+    }
+    let names: Vec<&str> = chain.iter().map(|source| source.name()).collect();
+    log::info!("Asset sources: {}", names.join(" -> "));
+    chain
+}
+
+/// Files from the active resource packs, found through the redirect table
+pub struct PackSource;
+impl AssetSource for PackSource {
+    fn name(&self) -> &str {
+        "resource packs"
+    }
+    fn lookup(&mut self, path: &Path) -> Option<Buffer> {
+        let pack_path = REDIRECTS.lock().ignore_poison().resolve(path)?;
+        let mut resource_loc = ResourceLocation::new();
+        let mut cpppath = ResourceLocation::get_path(&mut resource_loc);
+        opt_path_join(cpppath.as_mut(), &[&pack_path]);
+        let packm = crate::PACKM_OBJ.lock().ignore_poison();
+        let Some(packm) = packm.as_ref() else {
+            log::error!("ResourcePackManager ptr is null");
+            return None;
+        };
+        let Some(stack_str) = packm.load_resource(resource_loc) else {
+            log::info!("Cannot find file: {}", cpppath.as_ref());
+            return None;
+        };
+        log::info!("Loaded ResourcePack file: {}", cpppath.as_ref());
+        // ResourceLocation gets dropped (also cxx_storage if its not needed)
+        let buffer = BufferCursor::Cxx(Cursor::new(stack_str));
+        Some(Buffer::new(path.to_path_buf(), buffer))
+    }
+}
+
+/// Loose files in a folder laid out like the apk assets
+pub struct DirSource {
+    dir: PathBuf,
+}
+impl DirSource {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+impl AssetSource for DirSource {
+    fn name(&self) -> &str {
+        "override directory"
+    }
+    // The whole point is editing files while the game runs
+    fn cacheable(&self) -> bool {
+        false
+    }
+    fn lookup(&mut self, path: &Path) -> Option<Buffer> {
+        if !REDIRECTS
+            .lock()
+            .ignore_poison()
+            .is_allowed(path.as_os_str().as_bytes())
+        {
+            return None;
+        }
+        let full_path = self.dir.join(path);
+        match fs::read(&full_path) {
+            Ok(data) => {
+                log::info!("Loaded override file: {}", full_path.display());
+                let buffer = BufferCursor::Vec(Cursor::new(data));
+                Some(Buffer::new(path.to_path_buf(), buffer))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::error!("Cannot read override file {}: {e}", full_path.display());
+                None
+            }
+        }
+    }
+}
+
+/// Files from a .zip or .mcpack archive laid out like a resource pack
+pub struct ZipSource {
+    archive: ZipArchive<File>,
+    // Folder inside the archive that has the pack manifest
+    root: String,
+}
+impl ZipSource {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let archive = ZipArchive::open(path)?;
+        // Packs are often zipped together with their folder
+        let root = archive
+            .names()
+            .filter_map(|name| name.strip_suffix("manifest.json"))
+            .filter(|folder| folder.is_empty() || folder.ends_with('/'))
+            .min_by_key(|folder| folder.len())
+            .unwrap_or_default()
+            .to_owned();
+        log::info!("Opened archive {} (root: {root:?})", path.display());
+        Ok(Self { archive, root })
+    }
+}
+impl AssetSource for ZipSource {
+    fn name(&self) -> &str {
+        "archive"
+    }
+    fn lookup(&mut self, path: &Path) -> Option<Buffer> {
+        let pack_path = REDIRECTS.lock().ignore_poison().resolve(path)?;
+        let name = format!("{}{}", self.root, pack_path.to_str()?);
+        match self.archive.read(&name)? {
+            Ok(data) => {
+                log::info!("Loaded archive file: {name}");
+                let buffer = BufferCursor::Vec(Cursor::new(data));
+                Some(Buffer::new(path.to_path_buf(), buffer))
+            }
+            Err(e) => {
+                log::error!("Cannot read {name} from archive: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Files kept in memory, a fake provider for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemorySource {
+    name: &'static str,
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+#[cfg(test)]
+impl MemorySource {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            files: HashMap::new(),
+        }
+    }
+    pub fn with(mut self, path: impl Into<PathBuf>, data: &[u8]) -> Self {
+        self.files.insert(path.into(), data.to_vec());
+        self
+    }
+}
+#[cfg(test)]
+impl AssetSource for MemorySource {
+    fn name(&self) -> &str {
+        self.name
+    }
+    fn lookup(&mut self, path: &Path) -> Option<Buffer> {
+        let data = self.files.get(path)?.clone();
+        Some(Buffer::new(
+            path.to_path_buf(),
+            BufferCursor::Vec(Cursor::new(data)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain_names(opts: &Options) -> Vec<String> {
+        build_chain(opts)
+            .iter()
+            .map(|source| source.name().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn packs_alone_by_default() {
+        assert_eq!(chain_names(&Options::default()), ["resource packs"]);
+    }
+
+    #[test]
+    fn override_directory_goes_where_asked() {
+        let mut opts = Options {
+            override_dir: Some(PathBuf::from("/nonexistent")),
+            ..Options::default()
+        };
+        assert_eq!(chain_names(&opts), ["override directory", "resource packs"]);
+        opts.override_priority = OverridePriority::AfterPacks;
+        assert_eq!(chain_names(&opts), ["resource packs", "override directory"]);
+    }
+
+    #[test]
+    fn archives_that_cannot_be_opened_are_left_out() {
+        let opts = Options {
+            archive: Some(PathBuf::from("/nonexistent.mcpack")),
+            ..Options::default()
+        };
+        assert_eq!(chain_names(&opts), ["resource packs"]);
+    }
+
+    #[test]
+    fn memory_sources_only_have_their_files() {
+        let mut source = MemorySource::new("memory").with("a.json", b"{}");
+        assert!(source.lookup(Path::new("a.json")).is_some());
+        assert!(source.lookup(Path::new("b.json")).is_none());
+    }
+}
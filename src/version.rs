@@ -0,0 +1,149 @@
+// Minecraft release numbers, used to pick signatures meant for the running game
+use std::{fmt, str::FromStr};
+
+/// A release like 1.21.120.4, missing parts count as 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GameVersion([u32; 4]);
+
+impl GameVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32, build: u32) -> Self {
+        Self([major, minor, patch, build])
+    }
+    pub const fn major(self) -> u32 {
+        self.0[0]
+    }
+    /// Parse a version, also returning how many parts it was written with
+    pub fn parse_with_len(text: &str) -> Option<(Self, usize)> {
+        let text = text.strip_prefix(['v', 'V']).unwrap_or(text);
+        let mut parts = [0; 4];
+        let mut len = 0;
+        for part in text.split('.') {
+            let slot = parts.get_mut(len)?;
+            *slot = part.parse().ok()?;
+            len += 1;
+        }
+        Some((Self(parts), len))
+    }
+    /// The first version that does not start with the first `len` parts of this one
+    fn next_after(self, len: usize) -> Option<Self> {
+        let mut parts = self.0;
+        let index = len.checked_sub(1)?;
+        let last = parts.get_mut(index)?;
+        *last = last.checked_add(1)?;
+        for part in parts.iter_mut().skip(len) {
+            *part = 0;
+        }
+        Some(Self(parts))
+    }
+}
+
+impl FromStr for GameVersion {
+    type Err = ();
+    fn from_str(text: &str) -> Result<Self, ()> {
+        Self::parse_with_len(text)
+            .map(|(version, _)| version)
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for GameVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [major, minor, patch, build] = self.0;
+        write!(f, "{major}.{minor}.{patch}")?;
+        if build != 0 {
+            write!(f, ".{build}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Versions a signature applies to, `1.21.60-1.21.110` also covers every 1.21.110.x build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VersionRange {
+    min: Option<GameVersion>,
+    // Exclusive
+    end: Option<GameVersion>,
+}
+
+impl VersionRange {
+    /// Parse `*`, `1.21.120-`, `-1.21.50`, `1.19.50-1.21.50` or a single version
+    pub fn parse(text: &str) -> Option<Self> {
+        if text == "*" {
+            return Some(Self::default());
+        }
+        let (min, max) = text.split_once('-').unwrap_or((text, text));
+        // `*` is the way to cover every version
+        if min.is_empty() && max.is_empty() {
+            return None;
+        }
+        let min = match min {
+            "" => None,
+            min => Some(min.parse().ok()?),
+        };
+        let end = match max {
+            "" => None,
+            max => {
+                let (max, len) = GameVersion::parse_with_len(max)?;
+                Some(max.next_after(len)?)
+            }
+        };
+        Some(Self { min, end })
+    }
+    pub fn contains(&self, version: GameVersion) -> bool {
+        self.min.is_none_or(|min| version >= min) && self.end.is_none_or(|end| version < end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(text: &str) -> GameVersion {
+        text.parse().expect("valid version")
+    }
+
+    #[test]
+    fn versions_are_ordered_by_part() {
+        assert!(version("1.21.120") > version("1.21.60"));
+        assert!(version("1.21.60.21") > version("1.21.60"));
+        assert_eq!(version("v1.21"), GameVersion::new(1, 21, 0, 0));
+        assert!("1.21.x".parse::<GameVersion>().is_err());
+        assert!("1.2.3.4.5".parse::<GameVersion>().is_err());
+    }
+
+    #[test]
+    fn ranges_include_builds_of_their_last_version() {
+        let range = VersionRange::parse("1.19.50-1.21.110").expect("valid range");
+        assert!(range.contains(version("1.19.50")));
+        assert!(range.contains(version("1.21.110.5")));
+        assert!(!range.contains(version("1.21.111")));
+        assert!(!range.contains(version("1.19.40.2")));
+    }
+
+    #[test]
+    fn open_ranges() {
+        let from = VersionRange::parse("1.21.120-").expect("valid range");
+        assert!(from.contains(version("26.0.24")));
+        assert!(!from.contains(version("1.21.110")));
+        let until = VersionRange::parse("-1.21.50").expect("valid range");
+        assert!(until.contains(version("1.18.30")));
+        assert!(!until.contains(version("1.21.60")));
+        assert!(VersionRange::parse("*")
+            .expect("valid range")
+            .contains(version("1.0")));
+    }
+
+    #[test]
+    fn single_versions_cover_their_builds() {
+        let range = VersionRange::parse("1.21.60").expect("valid range");
+        assert!(range.contains(version("1.21.60.21")));
+        assert!(!range.contains(version("1.21.61")));
+    }
+
+    #[test]
+    fn invalid_ranges_are_rejected() {
+        for text in ["-", "", "1.21-x", "1.21.60-1.21.50-", "a-"] {
+            assert_eq!(VersionRange::parse(text), None, "{text}");
+        }
+    }
+}
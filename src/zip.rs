@@ -0,0 +1,255 @@
+// Minimal zip reader, enough to serve files out of .zip and .mcpack archives
+use scroll::{Pread, LE};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+const CENTRAL_SIGNATURE: u32 = 0x02014b50;
+const LOCAL_SIGNATURE: u32 = 0x04034b50;
+const EOCD_LEN: usize = 22;
+const CENTRAL_LEN: usize = 46;
+const LOCAL_LEN: usize = 30;
+// The eocd is followed by a comment of up to u16::MAX bytes
+const MAX_EOCD_SEARCH: u64 = EOCD_LEN as u64 + u16::MAX as u64;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const FLAG_ENCRYPTED: u16 = 1;
+
+#[derive(Debug, Clone, Copy)]
+struct ZipEntry {
+    method: u16,
+    flags: u16,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    local_header_offset: u32,
+}
+
+/// A zip archive with its central directory indexed
+pub struct ZipArchive<R> {
+    reader: R,
+    // Sizes from the archive are checked against this before anything gets allocated
+    len: u64,
+    entries: HashMap<String, ZipEntry>,
+}
+
+impl ZipArchive<File> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        let search_len = file_len.min(MAX_EOCD_SEARCH);
+        let mut tail = vec![0; search_len as usize];
+        reader.seek(SeekFrom::Start(file_len - search_len))?;
+        reader.read_exact(&mut tail)?;
+        let eocd = tail
+            .windows(4)
+            .rposition(|sig| sig == EOCD_SIGNATURE.to_le_bytes())
+            .and_then(|pos| tail.get(pos..))
+            .ok_or_else(|| invalid("Cannot find end of central directory"))?;
+        let entry_count: u16 = eocd.pread_with(10, LE).map_err(invalid)?;
+        let central_len: u32 = eocd.pread_with(12, LE).map_err(invalid)?;
+        let central_offset: u32 = eocd.pread_with(16, LE).map_err(invalid)?;
+        if central_offset == u32::MAX || entry_count == u16::MAX {
+            return Err(invalid("Zip64 archives are not supported"));
+        }
+        if u64::from(central_offset) + u64::from(central_len) > file_len {
+            return Err(invalid(
+                "Central directory goes past the end of the archive",
+            ));
+        }
+        let mut central = vec![0; central_len as usize];
+        reader.seek(SeekFrom::Start(central_offset.into()))?;
+        reader.read_exact(&mut central)?;
+        let mut entries = HashMap::with_capacity(entry_count.into());
+        let offset = &mut 0;
+        for _ in 0..entry_count {
+            let (name, entry) = read_central_entry(&central, offset).map_err(invalid)?;
+            // Folders have no data to serve
+            if !name.ends_with('/') {
+                entries.insert(name, entry);
+            }
+        }
+        Ok(Self {
+            reader,
+            len: file_len,
+            entries,
+        })
+    }
+    /// Names of every file in the archive
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+    /// Read and decompress a file, None if the archive does not have it
+    pub fn read(&mut self, name: &str) -> Option<io::Result<Vec<u8>>> {
+        let entry = *self.entries.get(name)?;
+        Some(self.read_entry(entry))
+    }
+    fn read_entry(&mut self, entry: ZipEntry) -> io::Result<Vec<u8>> {
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            return Err(invalid("Encrypted entries are not supported"));
+        }
+        // The local header can have a different extra field than the central one
+        let mut local = [0; LOCAL_LEN];
+        self.reader
+            .seek(SeekFrom::Start(entry.local_header_offset.into()))?;
+        self.reader.read_exact(&mut local)?;
+        let signature: u32 = local.pread_with(0, LE).map_err(invalid)?;
+        if signature != LOCAL_SIGNATURE {
+            return Err(invalid("Invalid local header signature"));
+        }
+        let name_len: u16 = local.pread_with(26, LE).map_err(invalid)?;
+        let extra_len: u16 = local.pread_with(28, LE).map_err(invalid)?;
+        let data_start = self.reader.seek(SeekFrom::Current(
+            i64::from(name_len) + i64::from(extra_len),
+        ))?;
+        if data_start + u64::from(entry.compressed_size) > self.len {
+            return Err(invalid("Entry goes past the end of the archive"));
+        }
+        let mut data = vec![0; entry.compressed_size as usize];
+        self.reader.read_exact(&mut data)?;
+        match entry.method {
+            METHOD_STORED => Ok(data),
+            METHOD_DEFLATED => miniz_oxide::inflate::decompress_to_vec_with_limit(
+                &data,
+                entry.uncompressed_size as usize,
+            )
+            .map_err(|e| invalid(format!("Cannot inflate entry: {e}"))),
+            _ => Err(invalid("Unsupported compression method")),
+        }
+    }
+}
+
+fn read_central_entry(src: &[u8], offset: &mut usize) -> Result<(String, ZipEntry), scroll::Error> {
+    let header = src.get(*offset..).unwrap_or_default();
+    if header.len() < CENTRAL_LEN {
+        return Err(scroll::Error::TooBig {
+            size: CENTRAL_LEN,
+            len: header.len(),
+        });
+    }
+    let signature: u32 = header.pread_with(0, LE)?;
+    if signature != CENTRAL_SIGNATURE {
+        return Err(scroll::Error::BadInput {
+            size: *offset,
+            msg: "Invalid central directory signature",
+        });
+    }
+    let entry = ZipEntry {
+        flags: header.pread_with(8, LE)?,
+        method: header.pread_with(10, LE)?,
+        compressed_size: header.pread_with(20, LE)?,
+        uncompressed_size: header.pread_with(24, LE)?,
+        local_header_offset: header.pread_with(42, LE)?,
+    };
+    let name_len: u16 = header.pread_with(28, LE)?;
+    let extra_len: u16 = header.pread_with(30, LE)?;
+    let comment_len: u16 = header.pread_with(32, LE)?;
+    let name = header
+        .get(CENTRAL_LEN..CENTRAL_LEN + usize::from(name_len))
+        .ok_or(scroll::Error::TooBig {
+            size: name_len.into(),
+            len: header.len(),
+        })?;
+    // Zip paths are always utf8 in practice, windows tools may still use backslashes
+    let name = String::from_utf8_lossy(name).replace('\\', "/");
+    *offset +=
+        CENTRAL_LEN + usize::from(name_len) + usize::from(extra_len) + usize::from(comment_len);
+    Ok((name, entry))
+}
+
+fn invalid(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Cursor, path::PathBuf};
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/zip")
+            .join(name);
+        fs::read(path).expect("zip fixture")
+    }
+
+    fn archive(name: &str) -> ZipArchive<Cursor<Vec<u8>>> {
+        ZipArchive::new(Cursor::new(fixture(name))).expect("valid archive")
+    }
+
+    #[test]
+    fn files_are_indexed_without_folders() {
+        for name in ["stored.zip", "deflated.zip"] {
+            let archive = archive(name);
+            let mut names: Vec<&str> = archive.names().collect();
+            names.sort_unstable();
+            assert_eq!(
+                names,
+                [
+                    "MyPack/manifest.json",
+                    "MyPack/renderer/materials/Sky.material.bin",
+                    "MyPack/texts/en_US.lang",
+                ],
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn stored_and_deflated_entries_read_the_same() {
+        let sky = b"sky material ".repeat(40);
+        for name in ["stored.zip", "deflated.zip"] {
+            let mut archive = archive(name);
+            let read = |archive: &mut ZipArchive<_>, file| {
+                archive
+                    .read(file)
+                    .map(|data| data.unwrap_or_else(|e| panic!("{name} {file}: {e}")))
+            };
+            assert_eq!(
+                read(&mut archive, "MyPack/renderer/materials/Sky.material.bin"),
+                Some(sky.clone())
+            );
+            assert_eq!(
+                read(&mut archive, "MyPack/texts/en_US.lang").as_deref(),
+                Some(b"pack.name=My Pack\n".as_slice())
+            );
+            assert_eq!(read(&mut archive, "MyPack/missing.json"), None);
+        }
+    }
+
+    #[test]
+    fn truncated_archives_are_rejected() {
+        let data = fixture("stored.zip");
+        let cut = data.get(..data.len() / 2).expect("fixture").to_vec();
+        assert!(ZipArchive::new(Cursor::new(cut)).is_err());
+    }
+
+    #[test]
+    fn sizes_past_the_end_are_rejected_before_allocating() {
+        let mut data = fixture("stored.zip");
+        // Point the compressed size of every central entry at almost 4GB
+        let mut at = 0;
+        while let Some(pos) = data.get(at..).and_then(|rest| {
+            rest.windows(4)
+                .position(|sig| sig == CENTRAL_SIGNATURE.to_le_bytes())
+        }) {
+            let size_at = at + pos + 20;
+            data.splice(size_at..size_at + 4, 0xFFFF_FFF0u32.to_le_bytes());
+            at = size_at;
+        }
+        let mut archive = ZipArchive::new(Cursor::new(data)).expect("directory is intact");
+        let result = archive
+            .read("MyPack/texts/en_US.lang")
+            .expect("entry exists");
+        assert!(result.is_err());
+    }
+}
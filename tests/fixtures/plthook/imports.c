@@ -0,0 +1,12 @@
+typedef struct AAsset AAsset;
+typedef struct AAssetManager AAssetManager;
+extern AAsset *AAssetManager_open(AAssetManager *, const char *, int);
+extern int AAsset_read(AAsset *, void *, unsigned long);
+extern void AAsset_close(AAsset *);
+void *keep_read = (void *)&AAsset_read;
+int load(AAssetManager *m, char *buf) {
+    AAsset *a = AAssetManager_open(m, "x", 0);
+    int n = AAsset_read(a, buf, 4);
+    AAsset_close(a);
+    return n;
+}